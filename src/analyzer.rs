@@ -0,0 +1,220 @@
+//! Feeds the oscilloscope and spectrum analyzer.
+//!
+//! The audio thread copies frames into an [AnalyzerTap], which never blocks
+//! and simply forgets the oldest frames when the UI falls behind. The UI
+//! drains the tap into its own history and draws from that. [Spectrum] turns
+//! a window of that history into magnitudes per frequency.
+
+use crossbeam::queue::ArrayQueue;
+use groove_core::StereoSample;
+use std::{collections::VecDeque, fmt::Debug};
+
+/// A lock-free path for recent frames of the master output from the audio
+/// thread to the UI.
+///
+/// There's no way to tap a single entity yet. Entities report only their
+/// latest frame, and effects not even that, so watching one would mean
+/// ticking the orchestrator a frame at a time.
+pub struct AnalyzerTap {
+    frames: ArrayQueue<StereoSample>,
+}
+impl Debug for AnalyzerTap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AnalyzerTap")
+            .field("frames", &self.frames.len())
+            .finish()
+    }
+}
+impl AnalyzerTap {
+    /// Creates a tap that holds up to `capacity` frames between visits from
+    /// the UI.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            frames: ArrayQueue::new(capacity),
+        }
+    }
+
+    /// Adds `frames`, pushing out the oldest ones if there isn't room. Never
+    /// blocks.
+    pub fn push(&self, frames: &[StereoSample]) {
+        for frame in frames {
+            let _ = self.frames.force_push(*frame);
+        }
+    }
+
+    /// Moves everything waiting in the tap onto the end of `history`, then
+    /// trims `history` to its newest `max_len` frames. Returns how many frames
+    /// were moved.
+    pub fn drain_into(&self, history: &mut VecDeque<StereoSample>, max_len: usize) -> usize {
+        let mut count = 0;
+        while let Some(frame) = self.frames.pop() {
+            history.push_back(frame);
+            count += 1;
+        }
+        let excess = history.len().saturating_sub(max_len);
+        history.drain(..excess);
+        count
+    }
+}
+
+/// Computes the magnitude spectrum of a block of samples.
+#[derive(Debug)]
+pub struct Spectrum {
+    // A Hann window as long as the transform.
+    window: Vec<f64>,
+}
+impl Spectrum {
+    /// Prepares to analyze blocks of `len` samples, which must be a power of
+    /// two.
+    pub fn new(len: usize) -> Self {
+        assert!(
+            len.is_power_of_two(),
+            "FFT length {len} isn't a power of two"
+        );
+        Self {
+            window: (0..len)
+                .map(|i| 0.5 - 0.5 * (2.0 * std::f64::consts::PI * i as f64 / len as f64).cos())
+                .collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.window.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.window.is_empty()
+    }
+
+    /// Returns the level of each frequency bin from 0 up to (not including)
+    /// the Nyquist frequency, in dB relative to a full-scale sine. Bin `i` is
+    /// centered on `i * sample_rate / len` Hz. If `samples` is shorter than
+    /// the transform, it's padded with silence.
+    pub fn analyze(&self, samples: impl Iterator<Item = f64>) -> Vec<f64> {
+        let len = self.len();
+        let mut real: Vec<f64> = samples
+            .chain(std::iter::repeat(0.0))
+            .zip(self.window.iter())
+            .map(|(sample, window)| sample * window)
+            .collect();
+        let mut imaginary = vec![0.0; len];
+        fft(&mut real, &mut imaginary);
+
+        // A full-scale sine puts half its energy in each of two bins, and the
+        // Hann window halves its amplitude again.
+        let scale = 4.0 / len as f64;
+        real.iter()
+            .zip(imaginary.iter())
+            .take(len / 2)
+            .map(|(re, im)| 20.0 * ((re * re + im * im).sqrt() * scale).max(1e-12).log10())
+            .collect()
+    }
+}
+
+/// An in-place, iterative radix-2 FFT. Both slices must be the same
+/// power-of-two length.
+fn fft(real: &mut [f64], imaginary: &mut [f64]) {
+    let len = real.len();
+    debug_assert_eq!(len, imaginary.len());
+    debug_assert!(len.is_power_of_two());
+
+    // Put the input in bit-reversed order.
+    let mut j = 0;
+    for i in 1..len {
+        let mut bit = len >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            real.swap(i, j);
+            imaginary.swap(i, j);
+        }
+    }
+
+    let mut size = 2;
+    while size <= len {
+        let angle = -2.0 * std::f64::consts::PI / size as f64;
+        let (step_im, step_re) = angle.sin_cos();
+        for start in (0..len).step_by(size) {
+            let (mut w_re, mut w_im) = (1.0, 0.0);
+            for k in 0..size / 2 {
+                let even = start + k;
+                let odd = even + size / 2;
+                let t_re = real[odd] * w_re - imaginary[odd] * w_im;
+                let t_im = real[odd] * w_im + imaginary[odd] * w_re;
+                real[odd] = real[even] - t_re;
+                imaginary[odd] = imaginary[even] - t_im;
+                real[even] += t_re;
+                imaginary[even] += t_im;
+                (w_re, w_im) = (
+                    w_re * step_re - w_im * step_im,
+                    w_re * step_im + w_im * step_re,
+                );
+            }
+        }
+        size <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use groove_core::Sample;
+
+    #[test]
+    fn tap_keeps_the_newest_frames() {
+        let tap = AnalyzerTap::new(4);
+        let frames: Vec<StereoSample> = (0..6)
+            .map(|i| StereoSample(Sample(i as f64), Sample(-(i as f64))))
+            .collect();
+        tap.push(&frames);
+
+        let mut history = VecDeque::from(vec![StereoSample::SILENCE; 3]);
+        assert_eq!(tap.drain_into(&mut history, 5), 4);
+        let lefts: Vec<f64> = history.iter().map(|frame| frame.0 .0).collect();
+        assert_eq!(lefts, vec![0.0, 2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(tap.drain_into(&mut history, 5), 0);
+    }
+
+    #[test]
+    fn fft_matches_a_direct_transform() {
+        let len = 16;
+        let input: Vec<f64> = (0..len).map(|i| ((i * 7) % 5) as f64 - 2.0).collect();
+        let mut real = input.clone();
+        let mut imaginary = vec![0.0; len];
+        fft(&mut real, &mut imaginary);
+        for k in 0..len {
+            let (mut re, mut im) = (0.0, 0.0);
+            for (n, x) in input.iter().enumerate() {
+                let angle = -2.0 * std::f64::consts::PI * (k * n) as f64 / len as f64;
+                re += x * angle.cos();
+                im += x * angle.sin();
+            }
+            assert!((real[k] - re).abs() < 1e-9 && (imaginary[k] - im).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn spectrum_finds_a_sine() {
+        let sample_rate = 48000.0;
+        let spectrum = Spectrum::new(4096);
+        let bin = 100;
+        let frequency = bin as f64 * sample_rate / spectrum.len() as f64;
+        let levels = spectrum.analyze((0..spectrum.len()).map(|i| {
+            0.5 * (2.0 * std::f64::consts::PI * frequency * i as f64 / sample_rate).sin()
+        }));
+        assert_eq!(levels.len(), 2048);
+
+        // Half of full scale is about -6dB.
+        assert!((levels[bin] - -6.02).abs() < 0.1, "{} dB", levels[bin]);
+        let loudest_elsewhere = levels
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| i.abs_diff(bin) > 2)
+            .map(|(_, level)| *level)
+            .fold(f64::NEG_INFINITY, f64::max);
+        assert!(loudest_elsewhere < -60.0, "{loudest_elsewhere} dB");
+    }
+}
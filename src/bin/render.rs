@@ -0,0 +1,188 @@
+//! Renders a project to a WAV file without opening a window.
+
+use egui_prototype::{
+    meter,
+    render::{self, RenderOutcome, RenderProgress, RenderSettings, WavFormat},
+};
+use std::path::PathBuf;
+
+const USAGE: &str = "usage: render <project.yaml> <assets-dir> <output.wav> \
+    [--sample-rate <hz>] [--format <16|24|32f>] [--max-seconds <seconds>] \
+    [--target-lufs <lufs> [--tolerance <lu>]]";
+
+/// Everything the command line tells us about what to render.
+#[derive(Debug)]
+struct Args {
+    song: PathBuf,
+    assets_path: PathBuf,
+    settings: RenderSettings,
+
+    // The --sample-rate option, if given. Otherwise the file is written at
+    // the project's own rate.
+    sample_rate: Option<usize>,
+
+    // If given, the render fails unless its integrated loudness is within
+    // `tolerance` LU of this.
+    target_lufs: Option<f64>,
+    tolerance: f64,
+}
+impl Args {
+    const DEFAULT_TOLERANCE: f64 = 1.0;
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> anyhow::Result<Args> {
+    let mut positional = Vec::default();
+    let mut settings = RenderSettings::default();
+    let mut sample_rate = None;
+    let mut target_lufs = None;
+    let mut tolerance = Args::DEFAULT_TOLERANCE;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--sample-rate" => {
+                let rate: usize = next_value(&mut args, &arg)?.parse()?;
+                if rate == 0 {
+                    return Err(anyhow::anyhow!("--sample-rate must be above zero"));
+                }
+                sample_rate = Some(rate);
+            }
+            "--format" => {
+                settings.format = match next_value(&mut args, &arg)?.as_str() {
+                    "16" => WavFormat::Int16,
+                    "24" => WavFormat::Int24,
+                    "32f" => WavFormat::Float32,
+                    other => return Err(anyhow::anyhow!("Unknown format {other}")),
+                };
+            }
+            "--max-seconds" => {
+                settings.max_seconds = next_value(&mut args, &arg)?.parse()?;
+            }
+            "--target-lufs" => {
+                target_lufs = Some(next_value(&mut args, &arg)?.parse()?);
+            }
+            "--tolerance" => {
+                tolerance = next_value(&mut args, &arg)?.parse()?;
+            }
+            _ if arg.starts_with("--") => return Err(anyhow::anyhow!("Unknown option {arg}")),
+            _ => positional.push(arg),
+        }
+    }
+    let [song, assets_path, output]: [String; 3] = positional
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Expected exactly three paths"))?;
+    settings.path = PathBuf::from(output);
+    Ok(Args {
+        song: PathBuf::from(song),
+        assets_path: PathBuf::from(assets_path),
+        settings,
+        sample_rate,
+        target_lufs,
+        tolerance,
+    })
+}
+
+fn next_value(args: &mut impl Iterator<Item = String>, option: &str) -> anyhow::Result<String> {
+    args.next()
+        .ok_or_else(|| anyhow::anyhow!("{option} needs a value"))
+}
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{err}\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    let mut settings = args.settings;
+    settings.project_sample_rate = render::project_sample_rate(&args.song)?;
+    settings.sample_rate = args.sample_rate.unwrap_or(settings.project_sample_rate);
+    let mut orchestrator = render::load_song(&args.song, &args.assets_path)?;
+    let progress = RenderProgress::default();
+    let outcome = render::render_to_wav(&mut orchestrator, &settings, &progress)?;
+    if let RenderOutcome::Finished(frames) = outcome {
+        let reading = progress.meter();
+        println!(
+            "Rendered {:0.1}s of {} to {}",
+            frames as f64 / settings.sample_rate as f64,
+            args.song.display(),
+            settings.path.display()
+        );
+        println!(
+            "Integrated loudness {:0.1} LUFS, peak {:0.1} dBFS",
+            reading.integrated_lufs,
+            meter::to_dbfs(reading.max_peak[0].max(reading.max_peak[1]))
+        );
+        if let Some(target) = args.target_lufs {
+            if (reading.integrated_lufs - target).abs() > args.tolerance {
+                return Err(anyhow::anyhow!(
+                    "Integrated loudness {:0.1} LUFS is outside {target} ± {} LU",
+                    reading.integrated_lufs,
+                    args.tolerance
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> anyhow::Result<Args> {
+        parse_args(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn parses_paths_and_options() {
+        let args = parse(&[
+            "song.yaml",
+            "assets",
+            "out.wav",
+            "--sample-rate",
+            "48000",
+            "--format",
+            "32f",
+        ])
+        .unwrap();
+        assert_eq!(args.song, PathBuf::from("song.yaml"));
+        assert_eq!(args.assets_path, PathBuf::from("assets"));
+        assert_eq!(args.settings.path, PathBuf::from("out.wav"));
+        assert_eq!(args.sample_rate, Some(48000));
+        assert_eq!(args.settings.format, WavFormat::Float32);
+    }
+
+    #[test]
+    fn sample_rate_is_optional() {
+        let args = parse(&["song.yaml", "assets", "out.wav"]).unwrap();
+        assert_eq!(args.sample_rate, None);
+        assert_eq!(args.target_lufs, None);
+    }
+
+    #[test]
+    fn parses_loudness_target() {
+        let args = parse(&[
+            "song.yaml",
+            "assets",
+            "out.wav",
+            "--target-lufs",
+            "-14",
+            "--tolerance",
+            "0.5",
+        ])
+        .unwrap();
+        assert_eq!(args.target_lufs, Some(-14.0));
+        assert_eq!(args.tolerance, 0.5);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(parse(&["song.yaml", "assets"]).is_err());
+        assert!(parse(&["song.yaml", "assets", "out.wav", "--format", "12"]).is_err());
+        assert!(parse(&["song.yaml", "assets", "out.wav", "--sample-rate"]).is_err());
+        assert!(parse(&["song.yaml", "assets", "out.wav", "--sample-rate", "0"]).is_err());
+        assert!(parse(&["song.yaml", "assets", "out.wav", "--bogus"]).is_err());
+    }
+}
@@ -0,0 +1,138 @@
+//! A metronome for the cue bus.
+//!
+//! [Click] doesn't keep time on its own. Whoever drives it passes in the
+//! song position for each batch of frames, so the clicks follow the song
+//! through tempo changes, loops, and skips.
+
+use groove_core::{Sample, StereoSample};
+
+/// Makes a short blip at the start of every beat.
+#[derive(Debug)]
+pub struct Click {
+    sample_rate: usize,
+
+    // The song position, in beats, of the last frame we filled, or None if
+    // the song wasn't playing then.
+    last_beat: Option<f64>,
+
+    // How far into the current blip we are, if one is sounding.
+    frames_into_click: Option<usize>,
+}
+impl Click {
+    /// How long each blip lasts.
+    const CLICK_SECONDS: f64 = 0.02;
+
+    /// The pitch of each blip.
+    const CLICK_FREQUENCY: f64 = 1000.0;
+
+    /// How loud each blip starts out.
+    const CLICK_LEVEL: f64 = 0.5;
+
+    pub fn new(sample_rate: usize) -> Self {
+        Self {
+            sample_rate,
+            last_beat: None,
+            frames_into_click: None,
+        }
+    }
+
+    /// Fills `samples` with clicks. `beat` is the song position, in beats,
+    /// at the first of them, or None if the song isn't playing, in which case
+    /// they're silent. `beats_per_frame` is how far each frame moves the
+    /// song along.
+    pub fn fill(&mut self, samples: &mut [StereoSample], beat: Option<f64>, beats_per_frame: f64) {
+        let Some(first_beat) = beat else {
+            samples.fill(StereoSample::SILENCE);
+            self.last_beat = None;
+            self.frames_into_click = None;
+            return;
+        };
+        let click_frames = (Self::CLICK_SECONDS * self.sample_rate as f64) as usize;
+        for (i, sample) in samples.iter_mut().enumerate() {
+            let beat = first_beat + i as f64 * beats_per_frame;
+
+            // Clicks when the song moves on into the next beat, or jumps to
+            // the start of one.
+            let is_next_beat = self
+                .last_beat
+                .is_some_and(|last| beat.floor() == last.floor() + 1.0);
+            let is_other_beat = self
+                .last_beat
+                .map_or(true, |last| beat.floor() != last.floor());
+            if is_next_beat || (is_other_beat && beat.fract() < beats_per_frame) {
+                self.frames_into_click = Some(0);
+            }
+            self.last_beat = Some(beat);
+
+            let value = match self.frames_into_click {
+                Some(frame) if frame < click_frames => {
+                    self.frames_into_click = Some(frame + 1);
+                    let t = frame as f64 / self.sample_rate as f64;
+                    let decay = 1.0 - frame as f64 / click_frames as f64;
+                    Self::CLICK_LEVEL
+                        * decay
+                        * (2.0 * std::f64::consts::PI * Self::CLICK_FREQUENCY * t).sin()
+                }
+                _ => {
+                    self.frames_into_click = None;
+                    0.0
+                }
+            };
+            *sample = StereoSample(Sample(value), Sample(value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the frames at which clicks start.
+    fn click_starts(samples: &[StereoSample]) -> Vec<usize> {
+        let mut starts = Vec::default();
+        let mut silent_frames = usize::MAX;
+        for (i, sample) in samples.iter().enumerate() {
+            // The blip is a sine that starts at zero, so look for the first
+            // sound after a stretch of silence and step back one frame.
+            if sample.0 .0 != 0.0 {
+                if silent_frames > 10 {
+                    starts.push(i.saturating_sub(1));
+                }
+                silent_frames = 0;
+            } else {
+                silent_frames = silent_frames.saturating_add(1);
+            }
+        }
+        starts
+    }
+
+    // A beat every 256 frames, which keeps the arithmetic exact.
+    const SAMPLE_RATE: usize = 1024;
+    const BEATS_PER_FRAME: f64 = 1.0 / 256.0;
+
+    #[test]
+    fn clicks_on_every_beat() {
+        let mut click = Click::new(SAMPLE_RATE);
+        let mut samples = [StereoSample::SILENCE; 600];
+        click.fill(&mut samples, Some(0.0), BEATS_PER_FRAME);
+        assert_eq!(click_starts(&samples), vec![0, 256, 512]);
+    }
+
+    #[test]
+    fn follows_the_song_position() {
+        let mut click = Click::new(SAMPLE_RATE);
+
+        // Starting partway through a beat waits for the next one.
+        let mut samples = [StereoSample::SILENCE; 200];
+        click.fill(&mut samples, Some(2.5), BEATS_PER_FRAME);
+        assert_eq!(click_starts(&samples), vec![128]);
+
+        // Jumping back to the top clicks right away.
+        click.fill(&mut samples, Some(0.0), BEATS_PER_FRAME);
+        assert_eq!(click_starts(&samples), vec![0]);
+
+        // Stopped means silent.
+        click.fill(&mut samples, None, BEATS_PER_FRAME);
+        assert!(samples.iter().all(|s| s.0 .0 == 0.0 && s.1 .0 == 0.0));
+    }
+}
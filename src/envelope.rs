@@ -0,0 +1,287 @@
+//! The geometry behind the graphical envelope editor.
+//!
+//! [Adsr] is the shape the editor draws and drags around, and [VoiceTracker]
+//! remembers the notes the UI has played so that the editor can show where
+//! each one is along that shape. The curve is drawn with straight lines,
+//! which is close enough to show the user what they're changing.
+//!
+//! Only the editor's own test notes get playheads. Notes that the song plays
+//! reach the instruments inside the audio thread's orchestrator, which
+//! doesn't say where its voices are, so the editor can't show them.
+
+use std::time::Instant;
+
+/// The parameters of an ADSR envelope. The stages are in seconds, and
+/// sustain is a level from 0 to 1.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Adsr {
+    pub attack: f64,
+    pub decay: f64,
+    pub sustain: f64,
+    pub release: f64,
+}
+
+/// A corner of the envelope that the user can drag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// The peak at the end of the attack. Moves sideways only.
+    Attack,
+
+    /// The start of the sustain. Sideways changes the decay, and up and down
+    /// changes the sustain level.
+    Decay,
+
+    /// The end of the release. Moves sideways only.
+    Release,
+}
+
+impl Adsr {
+    /// The longest any one stage can be.
+    pub const MAX_STAGE_SECONDS: f64 = 10.0;
+
+    /// Returns the level `seconds` after the note started. If the note has
+    /// been released, `released` is how long after it started that happened.
+    pub fn level_at(&self, seconds: f64, released: Option<f64>) -> f64 {
+        match released {
+            Some(released) if seconds >= released => {
+                let from = self.held_level_at(released);
+                if self.release <= 0.0 {
+                    0.0
+                } else {
+                    (from * (1.0 - (seconds - released) / self.release)).max(0.0)
+                }
+            }
+            _ => self.held_level_at(seconds),
+        }
+    }
+
+    fn held_level_at(&self, seconds: f64) -> f64 {
+        if seconds < self.attack {
+            seconds / self.attack
+        } else if seconds < self.attack + self.decay {
+            1.0 - (1.0 - self.sustain) * (seconds - self.attack) / self.decay
+        } else {
+            self.sustain
+        }
+    }
+
+    /// Returns the corners of the envelope as [time, level] pairs, for a note
+    /// that's held at the sustain level for `hold` seconds.
+    pub fn outline(&self, hold: f64) -> [[f64; 2]; 5] {
+        let sustain_start = self.attack + self.decay;
+        let release_start = sustain_start + hold;
+        [
+            [0.0, 0.0],
+            [self.attack, 1.0],
+            [sustain_start, self.sustain],
+            [release_start, self.sustain],
+            [release_start + self.release, 0.0],
+        ]
+    }
+
+    /// Returns where `breakpoint` is on the [Adsr::outline()] for `hold`.
+    pub fn breakpoint(&self, breakpoint: Breakpoint, hold: f64) -> [f64; 2] {
+        let outline = self.outline(hold);
+        match breakpoint {
+            Breakpoint::Attack => outline[1],
+            Breakpoint::Decay => outline[2],
+            Breakpoint::Release => outline[4],
+        }
+    }
+
+    /// Moves `breakpoint` as close as it can go to `time` and `level` on the
+    /// [Adsr::outline()] for `hold`, leaving the other breakpoints where
+    /// they are.
+    pub fn drag(&mut self, breakpoint: Breakpoint, time: f64, level: f64, hold: f64) {
+        let clamp = |seconds: f64| seconds.clamp(0.0, Self::MAX_STAGE_SECONDS);
+        match breakpoint {
+            Breakpoint::Attack => self.attack = clamp(time),
+            Breakpoint::Decay => {
+                self.decay = clamp(time - self.attack);
+                self.sustain = level.clamp(0.0, 1.0);
+            }
+            Breakpoint::Release => {
+                self.release = clamp(time - (self.attack + self.decay + hold));
+            }
+        }
+    }
+
+    /// Returns where a voice belongs on the [Adsr::outline()] for `hold`, as
+    /// a [time, level] pair. A voice that's still held past the decay waits
+    /// at the end of the sustain, and a released one follows the release
+    /// from wherever it was let go.
+    pub fn playhead(&self, voice: &VoicePosition, hold: f64) -> [f64; 2] {
+        let sustain_start = self.attack + self.decay;
+        let level = self.level_at(voice.seconds, voice.released);
+        let time = match voice.released {
+            Some(released) if voice.seconds >= released => {
+                sustain_start + hold + (voice.seconds - released)
+            }
+            _ if voice.seconds < sustain_start => voice.seconds,
+            _ => sustain_start + (voice.seconds - sustain_start).min(hold),
+        };
+        [time, level]
+    }
+
+    /// Returns whether a voice at `voice` still makes any sound.
+    pub fn is_sounding(&self, voice: &VoicePosition) -> bool {
+        voice
+            .released
+            .map_or(true, |released| voice.seconds < released + self.release)
+    }
+}
+
+/// How far along a voice is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VoicePosition {
+    /// How long ago the note started.
+    pub seconds: f64,
+
+    /// How long after it started the note was released, if it has been.
+    pub released: Option<f64>,
+}
+
+#[derive(Clone, Debug)]
+struct Voice {
+    key: u8,
+    started: Instant,
+    released: Option<Instant>,
+}
+
+/// The notes that the UI has played on an instrument, and when.
+#[derive(Clone, Debug, Default)]
+pub struct VoiceTracker {
+    voices: Vec<Voice>,
+}
+impl VoiceTracker {
+    /// Starts a voice for `key`. Playing a key that's already down starts
+    /// it over.
+    pub fn note_on(&mut self, key: u8, when: Instant) {
+        self.voices.retain(|voice| voice.key != key);
+        self.voices.push(Voice {
+            key,
+            started: when,
+            released: None,
+        });
+    }
+
+    /// Releases the voice for `key`, if it's down.
+    pub fn note_off(&mut self, key: u8, when: Instant) {
+        for voice in self.voices.iter_mut() {
+            if voice.key == key && voice.released.is_none() {
+                voice.released = Some(when);
+            }
+        }
+    }
+
+    /// Returns where each voice is as of `now`, oldest first, including
+    /// voices that no envelope could still be sounding.
+    pub fn positions(&self, now: Instant) -> Vec<VoicePosition> {
+        self.voices
+            .iter()
+            .map(|voice| VoicePosition {
+                seconds: now.saturating_duration_since(voice.started).as_secs_f64(),
+                released: voice.released.map(|released| {
+                    released
+                        .saturating_duration_since(voice.started)
+                        .as_secs_f64()
+                }),
+            })
+            .collect()
+    }
+
+    /// Forgets voices that were released too long ago for any envelope to
+    /// still be sounding.
+    pub fn prune(&mut self, now: Instant) {
+        self.voices.retain(|voice| {
+            voice.released.map_or(true, |released| {
+                now.saturating_duration_since(released).as_secs_f64() < Adsr::MAX_STAGE_SECONDS
+            })
+        });
+    }
+
+    /// Returns whether `key` has been played and not yet released.
+    pub fn is_held(&self, key: u8) -> bool {
+        self.voices
+            .iter()
+            .any(|voice| voice.key == key && voice.released.is_none())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.voices.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const ADSR: Adsr = Adsr {
+        attack: 0.1,
+        decay: 0.2,
+        sustain: 0.5,
+        release: 0.4,
+    };
+
+    #[test]
+    fn levels_follow_the_stages() {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        assert!(close(ADSR.level_at(0.05, None), 0.5));
+        assert!(close(ADSR.level_at(0.1, None), 1.0));
+        assert!(close(ADSR.level_at(0.2, None), 0.75));
+        assert!(close(ADSR.level_at(5.0, None), 0.5));
+
+        // Released at the sustain level, and halfway through the release.
+        assert!(close(ADSR.level_at(1.2, Some(1.0)), 0.25));
+
+        // Released halfway up the attack, so the release starts from there.
+        assert!(close(ADSR.level_at(0.25, Some(0.05)), 0.25));
+        assert!(close(ADSR.level_at(0.5, Some(0.05)), 0.0));
+    }
+
+    #[test]
+    fn dragging_moves_only_one_breakpoint() {
+        let hold = 1.0;
+        let mut adsr = ADSR;
+        adsr.drag(Breakpoint::Decay, 0.6, 0.8, hold);
+        assert_eq!(adsr.attack, 0.1);
+        assert!((adsr.decay - 0.5).abs() < 1e-9);
+        assert_eq!(adsr.sustain, 0.8);
+        assert_eq!(adsr.breakpoint(Breakpoint::Decay, hold), [0.6, 0.8]);
+
+        // The release can't go negative, and nothing goes past the limit.
+        adsr.drag(Breakpoint::Release, 0.0, 0.0, hold);
+        assert_eq!(adsr.release, 0.0);
+        adsr.drag(Breakpoint::Attack, 100.0, 0.0, hold);
+        assert_eq!(adsr.attack, Adsr::MAX_STAGE_SECONDS);
+        adsr.drag(Breakpoint::Decay, 0.0, 2.0, hold);
+        assert_eq!((adsr.decay, adsr.sustain), (0.0, 1.0));
+    }
+
+    #[test]
+    fn tracker_places_voices_on_the_outline() {
+        let start = Instant::now();
+        let mut tracker = VoiceTracker::default();
+        tracker.note_on(60, start);
+        tracker.note_on(64, start + Duration::from_millis(100));
+        tracker.note_off(60, start + Duration::from_secs(2));
+        assert!(!tracker.is_held(60) && tracker.is_held(64));
+
+        let hold = 0.5;
+        let positions = tracker.positions(start + Duration::from_millis(2200));
+        assert_eq!(positions.len(), 2);
+
+        // The first note was released 200ms ago, halfway through its release.
+        let [time, level] = ADSR.playhead(&positions[0], hold);
+        assert!((time - 1.0).abs() < 1e-6 && (level - 0.25).abs() < 1e-6);
+
+        // The second is still held, so it waits at the end of the sustain.
+        let [time, level] = ADSR.playhead(&positions[1], hold);
+        assert!((time - 0.8).abs() < 1e-6 && (level - 0.5).abs() < 1e-6);
+
+        assert!(!ADSR.is_sounding(&tracker.positions(start + Duration::from_secs(3))[0]));
+        tracker.prune(start + Duration::from_secs(30));
+        assert_eq!(tracker.positions(start).len(), 1);
+    }
+}
@@ -0,0 +1,10 @@
+pub mod analyzer;
+pub mod click;
+pub mod envelope;
+pub mod meter;
+pub mod params;
+pub mod profile;
+pub mod render;
+pub mod resample;
+pub mod schedule;
+pub mod stream;
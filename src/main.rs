@@ -1,29 +1,58 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
-use crossbeam_channel::Sender;
-use eframe::egui::{self, CollapsingHeader, ComboBox, DragValue, RichText, Slider, Ui};
+use cpal::HostId;
+use crossbeam::{atomic::AtomicCell, queue::ArrayQueue};
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use eframe::egui::{
+    self,
+    plot::{Line, Plot, PlotPoints},
+    CollapsingHeader, ComboBox, DragValue, RichText, Slider, Ui,
+};
+use egui_prototype::{
+    analyzer::{AnalyzerTap, Spectrum},
+    click::Click,
+    envelope::{Adsr, Breakpoint, VoicePosition, VoiceTracker},
+    meter::{self, Meter, MeterSnapshot},
+    params::{
+        edit_channel, EditLog, EditReceiver, EditRecorder, EditSender, TimedEdit, TransportSnapshot,
+    },
+    profile::{self, CpuLoad, EntityCost},
+    render::{self, RenderOutcome, RenderProgress, RenderSettings, WavFormat},
+    resample::Resampler,
+    schedule::Scheduler,
+    stream::{
+        self, AudioDeviceDescription, AudioDeviceId, AudioDeviceState, AudioInput, AudioInputEvent,
+        AudioInputStream, AudioInterfaceEvent, AudioInterfaceInput, AudioQueue, AudioStats,
+        AudioStream, AudioStreamError, AudioStreamService, ChannelMap, ChannelSource,
+        FileAudioInput, InputFeed, NullPacing, UnderrunCounter,
+    },
+};
 use groove_core::{
-    generators::{Envelope, Waveform},
+    generators::{EnvelopeNano, Waveform},
+    midi::{new_note_off, new_note_on},
     time::ClockNano,
-    traits::{Performs, Resets},
-    BipolarNormal, FrequencyHz, ParameterType, StereoSample, SAMPLE_BUFFER_SIZE,
+    traits::{Generates, HandlesMidi, Performs, Resets, Ticks, TicksWithMessages, TransformsAudio},
+    BipolarNormal, FrequencyHz, Normal, ParameterType, StereoSample, SAMPLE_BUFFER_SIZE,
 };
 use groove_entities::{
     controllers::LfoController,
-    effects::{BiQuadFilterLowPass24db, Mixer},
+    effects::{
+        BiQuadFilterAllPass, BiQuadFilterBandPass, BiQuadFilterBandStop, BiQuadFilterHighPass,
+        BiQuadFilterHighShelf, BiQuadFilterLowPass12db, BiQuadFilterLowPass24db,
+        BiQuadFilterLowShelf, BiQuadFilterNone, BiQuadFilterPeakingEq, Mixer,
+    },
     instruments::{Metronome, WelshSynth},
 };
-use groove_orchestration::Orchestrator;
-use groove_settings::SongSettings;
+use groove_orchestration::{messages::GrooveEvent, Orchestrator};
 use std::{
-    path::PathBuf,
-    sync::{Arc, Mutex, MutexGuard},
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread::JoinHandle,
+    time::{Duration, Instant},
 };
-use stream::{AudioInterfaceInput, AudioQueue, AudioStreamService};
 use strum::IntoEnumIterator;
 
-mod stream;
-
 fn main() -> Result<(), eframe::Error> {
     env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
     let options = eframe::NativeOptions {
@@ -33,198 +62,1839 @@ fn main() -> Result<(), eframe::Error> {
     eframe::run_native(
         "Audio Prototype (egui)",
         options,
-        Box::new(|_cc| Box::<AudioPrototype2>::default()),
+        Box::new(|cc| Box::new(AudioPrototype2::new(cc.egui_ctx.clone()))),
     )
 }
 
+/// What the audio thread works on. The UI changes it only through edits.
+struct AudioEngine {
+    orchestrator: Orchestrator,
+
+    // Captured input to mix into the output, if the user is monitoring it.
+    // It's mixed in after the orchestrator runs, so none of the project's
+    // entities see it. Routing it through effects or recording it would need
+    // an entity that pulls from the feed, which groove doesn't have yet.
+    monitored_input: Option<InputFeed>,
+}
+
+/// A project file, and every edit made to the UI's copy since it was loaded.
+#[derive(Clone, Debug)]
+struct LoadedSong {
+    path: PathBuf,
+
+    // The rate the project runs at. Read from the file when it's loaded, and
+    // chosen in the UI after that.
+    sample_rate: usize,
+
+    edits: EditLog<Orchestrator>,
+}
+impl LoadedSong {
+    /// Loads another copy of the song as it stands now, rather than as it was
+    /// saved.
+    fn instantiate(&self, assets_path: &Path) -> anyhow::Result<Orchestrator> {
+        let mut orchestrator = render::load_song(&self.path, assets_path)?;
+        self.edits.replay(&mut orchestrator);
+        Ok(orchestrator)
+    }
+}
+
 struct AudioPrototype2 {
-    orchestrator: Arc<Mutex<Orchestrator>>,
+    // The UI's copy of the project. The audio thread has its own copy, and
+    // every change made here is sent there through `edit_sender`. This copy
+    // is never ticked, so anything that moves while the song plays, like a
+    // parameter driven by an LFO, shows its last edited value here.
+    orchestrator: Orchestrator,
+    edit_sender: EditSender<AudioEngine>,
+
+    // What the audio thread's copy is doing right now.
+    transport: Arc<TransportSnapshot>,
+
+    // Events that the audio thread's copy has reported.
+    groove_events: Receiver<GrooveEvent>,
+
+    // The thread that generates audio. Taken when the app shuts down.
+    audio_thread: Option<JoinHandle<Result<(), AudioStreamError>>>,
+    event_log: EventLog,
+
     name: String,
     bpm: ParameterType,
-    sample_rate: Arc<Mutex<usize>>,
+
+    // The project file that the orchestrator was most recently loaded from,
+    // and what's been done to it since.
+    loaded_song: Option<LoadedSong>,
+
+    // What the audio stream service has been telling us. The audio thread
+    // passes its events along on `stream_events`, and the UI keeps track.
+    stream_events: Arc<ArrayQueue<AudioInterfaceEvent>>,
+    status: StatusLog,
+    stats: AudioStats,
+
+    // Levels of the audio thread's output.
+    meter: Arc<MeterSnapshot>,
+    analyzer_panel: AnalyzerPanel,
+
+    // How hard the audio thread is working.
+    cpu_load: Arc<CpuLoad>,
+    performance_panel: PerformancePanel,
 
     audio_stream_sender: Sender<AudioInterfaceInput>,
     control_bar: ControlBar,
+    device_picker: DevicePicker,
+    input_panel: InputPanel,
+    render_panel: RenderPanel,
+
+    tree: Tree,
+}
+impl AudioPrototype2 {
+    /// `ctx` lets the audio thread wake up the UI when something happens.
+    fn new(ctx: egui::Context) -> Self {
+        let clock_settings = ClockNano::default();
+        // Set AUDIO_BACKEND=null to run without a sound card.
+        let audio_stream_service = match std::env::var("AUDIO_BACKEND").as_deref() {
+            Ok("null") => AudioStreamService::new_null(NullPacing::RealTime),
+            _ => AudioStreamService::new(),
+        };
+        let audio_stream_sender = audio_stream_service.sender().clone();
+        // The UI and the audio thread each get a copy of the project. Only the
+        // audio thread's copy is ticked; the UI's copy just keeps up with
+        // edits so that it can show them.
+        let orchestrator = Orchestrator::new_with(clock_settings);
+        let (edit_sender, edit_receiver) = edit_channel(Self::EDIT_QUEUE_CAPACITY);
+        let transport = Arc::new(TransportSnapshot::default());
+        let (groove_event_sender, groove_events) = unbounded();
+        transport.set_project_sample_rate(render::DEFAULT_PROJECT_SAMPLE_RATE);
+        let stream_events = Arc::new(ArrayQueue::new(Self::STREAM_EVENT_CAPACITY));
+        let meter = Arc::new(MeterSnapshot::default());
+        let analyzer_tap = Arc::new(AnalyzerTap::new(AnalyzerPanel::TAP_CAPACITY));
+        let cpu_load = Arc::new(CpuLoad::default());
+        let stats = AudioStats::default();
+        let input_panel = InputPanel::default();
+        let audio_thread = Self::start_audio_stream(
+            AudioEngine {
+                orchestrator: Orchestrator::new_with(clock_settings),
+                monitored_input: None,
+            },
+            edit_receiver,
+            Arc::clone(&transport),
+            groove_event_sender,
+            ctx,
+            audio_stream_service,
+            Arc::clone(&stream_events),
+            stats.underrun_counter(),
+            Arc::clone(&meter),
+            Arc::clone(&analyzer_tap),
+            Arc::clone(&cpu_load),
+        );
+        Self {
+            bpm: Default::default(),
+            orchestrator,
+            edit_sender,
+            transport,
+            groove_events,
+            audio_thread: Some(audio_thread),
+            event_log: EventLog::default(),
+            name: "Arthur".to_owned(),
+
+            loaded_song: None,
+            stream_events,
+            status: StatusLog::default(),
+            stats,
+            meter,
+            analyzer_panel: AnalyzerPanel::new(analyzer_tap),
+            cpu_load,
+            performance_panel: PerformancePanel::default(),
+            audio_stream_sender,
+            control_bar: ControlBar::default(),
+            device_picker: DevicePicker::new(),
+            input_panel,
+            render_panel: RenderPanel::default(),
+            tree: Tree::demo(),
+        }
+    }
+}
+
+impl eframe::App for AudioPrototype2 {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.bpm = self.transport.bpm();
+        let mut edits = EditRecorder::default();
+        let mut engine_edits = EditRecorder::default();
+        self.handle_stream_events();
+        self.handle_groove_events();
+        let top = egui::TopBottomPanel::top("control-bar");
+        let status_bar = egui::TopBottomPanel::bottom("status-bar");
+        let bottom = egui::TopBottomPanel::bottom("orchestrator");
+        let left = egui::SidePanel::left("left-sidebar");
+        let center = egui::CentralPanel::default();
+
+        top.show(ctx, |ui| {
+            let device_state = self.stats.device_state();
+            self.control_bar.show(
+                ui,
+                &mut self.orchestrator,
+                &mut edits,
+                &self.transport,
+                &self.meter,
+                &self.audio_stream_sender,
+                device_state,
+            );
+        });
+        status_bar.show(ctx, |ui| self.status.show(ui));
+        bottom.show(ctx, |ui| {
+            ui.label(format!("clock: {:0.3}s", self.transport.seconds()));
+            if ui.button("load").clicked() {
+                self.handle_load(&mut edits);
+            }
+            self.show_project_sample_rate(ui);
+            self.render_panel
+                .show(ui, self.loaded_song.as_ref(), Path::new(Self::ASSETS_PATH));
+        });
+        left.show(ctx, |ui| {
+            CollapsingHeader::new("File browser")
+                .default_open(true)
+                .show(ui, |ui| self.tree.ui(ui));
+            CollapsingHeader::new("Audio device")
+                .default_open(false)
+                .show(ui, |ui| {
+                    self.device_picker.show(
+                        ui,
+                        &self.audio_stream_sender,
+                        self.stats.output_channels(),
+                    )
+                });
+            CollapsingHeader::new("Audio input")
+                .default_open(false)
+                .show(ui, |ui| {
+                    self.input_panel
+                        .show(ui, &mut self.status, &mut engine_edits)
+                });
+            CollapsingHeader::new("Event log")
+                .default_open(false)
+                .show(ui, |ui| self.event_log.show(ui));
+            CollapsingHeader::new("Analyzer")
+                .default_open(false)
+                .show(ui, |ui| {
+                    self.analyzer_panel.show(
+                        ui,
+                        self.transport.is_playing(),
+                        self.transport.project_sample_rate(),
+                    )
+                });
+            CollapsingHeader::new("Performance")
+                .default_open(false)
+                .show(ui, |ui| {
+                    self.performance_panel.show(
+                        ui,
+                        &self.cpu_load,
+                        self.loaded_song.as_ref(),
+                        self.transport.seconds(),
+                        Path::new(Self::ASSETS_PATH),
+                    )
+                });
+            CollapsingHeader::new("Diagnostics")
+                .default_open(false)
+                .show(ui, |ui| {
+                    ui.label(format!(
+                        "Output latency: {:0.1}ms",
+                        self.transport.output_latency() * 1000.0
+                    ));
+                    self.stats.show(ui);
+                });
+        });
+        center.show(ctx, |ui| {
+            self.orchestrator.show(ui, &mut edits);
+        });
+
+        self.send_edits(&mut edits);
+        for edit in engine_edits.take() {
+            self.edit_sender.send(edit);
+        }
+        if !self.edit_sender.flush() {
+            // The audio thread is behind, or paused along with the device.
+            // Come back in a little while to give it the rest.
+            ctx.request_repaint_after(Self::EDIT_RETRY_INTERVAL);
+        }
+    }
+
+    fn on_close_event(&mut self) -> bool {
+        // Give the audio threads a head start on shutting down. on_exit()
+        // waits for them.
+        let _ = self.audio_stream_sender.send(AudioInterfaceInput::Quit);
+        true
+    }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.shut_down();
+    }
+}
+impl AudioPrototype2 {
+    /// How many edits can be waiting for the audio thread before the UI has
+    /// to hold onto them.
+    const EDIT_QUEUE_CAPACITY: usize = 1024;
+
+    /// How long to wait before offering the audio thread edits that didn't
+    /// fit in its queue.
+    const EDIT_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+    /// How many audio stream events can be waiting for the UI. If it falls
+    /// further behind than this, it misses the oldest ones.
+    const STREAM_EVENT_CAPACITY: usize = 64;
+
+    /// Runs the audio thread, which owns `engine` from here on. The UI
+    /// changes it only through `edit_receiver`, and sees it only through
+    /// `transport` and the events that arrive on `groove_events`. Each event
+    /// also asks `ctx` for a repaint, so the UI handles it promptly even if
+    /// nothing else is going on. Everything the audio stream service reports
+    /// is passed along on `stream_events`, except underruns, which are
+    /// counted in `underruns` so that none are lost. How long each buffer
+    /// takes to generate goes to `cpu_load`. None of this ever waits on a
+    /// lock.
+    ///
+    /// The thread exits after the audio stream service quits, and returns
+    /// whatever went wrong while shutting the service down.
+    #[allow(clippy::too_many_arguments)]
+    fn start_audio_stream(
+        mut engine: AudioEngine,
+        edit_receiver: EditReceiver<AudioEngine>,
+        transport: Arc<TransportSnapshot>,
+        groove_events: Sender<GrooveEvent>,
+        ctx: egui::Context,
+        audio_stream_service: AudioStreamService,
+        stream_events: Arc<ArrayQueue<AudioInterfaceEvent>>,
+        underruns: Arc<UnderrunCounter>,
+        meter_snapshot: Arc<MeterSnapshot>,
+        analyzer_tap: Arc<AnalyzerTap>,
+        cpu_load: Arc<CpuLoad>,
+    ) -> JoinHandle<Result<(), AudioStreamError>> {
+        std::thread::spawn(move || {
+            let mut queue_opt = None;
+            let mut cue_opt = None;
+            let mut scheduler = Scheduler::default();
+
+            // The orchestrator always runs at the project's rate. This
+            // converts its output to whatever rate the device runs at.
+            let mut resampler = Resampler::new(
+                transport.project_sample_rate(),
+                transport.project_sample_rate(),
+            );
+            let mut meter = Meter::new(transport.project_sample_rate());
+            let mut click = Click::new(transport.project_sample_rate());
+
+            // Whether playback stopped because the song ended. If so, the
+            // next play starts from the top.
+            let mut reached_end = false;
+            while let Ok(event) = audio_stream_service.receiver().recv() {
+                // If the UI falls this far behind, it misses the oldest
+                // events rather than holding up the audio. Underruns are
+                // counted instead, so they can't be among those missed.
+                if !matches!(event, stream::AudioInterfaceEvent::Underrun(..)) {
+                    stream_events.force_push(event.clone());
+                }
+                match event {
+                    stream::AudioInterfaceEvent::Reset(sample_rate, queue, cue) => {
+                        let project_sample_rate = transport.project_sample_rate();
+                        engine.orchestrator.reset(project_sample_rate);
+                        resampler = Resampler::new(project_sample_rate, sample_rate);
+                        meter = Meter::new(sample_rate);
+                        click = Click::new(sample_rate);
+                        scheduler.reset(sample_rate, queue.capacity());
+                        queue_opt = Some(queue);
+                        cue_opt = Some(cue);
+                        ctx.request_repaint();
+                    }
+                    stream::AudioInterfaceEvent::NeedsAudio(when, count) => {
+                        let mut frame_count = scheduler.begin_buffer(when, count);
+                        while let Some(edit) = edit_receiver.pop() {
+                            scheduler.schedule(edit);
+                        }
+
+                        // Transport changes aren't tied to a moment, so
+                        // they're due now. Apply them before deciding
+                        // whether playback just started.
+                        let was_playing = engine.orchestrator.is_performing();
+                        scheduler.apply_due(&mut engine);
+
+                        // Loading a project can change its rate.
+                        let project_sample_rate = transport.project_sample_rate();
+                        if resampler.from_rate() != project_sample_rate {
+                            resampler = Resampler::new(project_sample_rate, resampler.to_rate());
+                        }
+                        if !was_playing && engine.orchestrator.is_performing() {
+                            if reached_end {
+                                engine.orchestrator.skip_to_start();
+                                reached_end = false;
+                            }
+
+                            // Whatever is still queued was generated
+                            // before the user pressed play. Throw it away
+                            // so that playback starts right now.
+                            if let (Some(queue), Some(cue)) = (queue_opt.as_ref(), cue_opt.as_ref())
+                            {
+                                while queue.pop().is_some() {}
+                                while cue.pop().is_some() {}
+                                frame_count =
+                                    scheduler.begin_buffer(Instant::now(), queue.capacity());
+                            }
+                        }
+                        if let (Some(queue), Some(cue)) = (queue_opt.as_ref(), cue_opt.as_ref()) {
+                            let started = Instant::now();
+                            let generated = Self::generate_audio(
+                                &mut engine,
+                                &mut scheduler,
+                                &mut resampler,
+                                &mut meter,
+                                &mut click,
+                                &analyzer_tap,
+                                queue,
+                                cue,
+                                &groove_events,
+                                transport.is_looping(),
+                                frame_count,
+                            );
+                            cpu_load.record(
+                                started.elapsed(),
+                                Duration::from_secs_f64(
+                                    frame_count as f64 / resampler.to_rate() as f64,
+                                ),
+                            );
+                            reached_end |= generated.reached_end;
+                            if generated.has_events || generated.reached_end {
+                                ctx.request_repaint();
+                            }
+                        }
+                        if meter_snapshot.take_clear_clips_request() {
+                            meter.clear_clips();
+                        }
+                        meter_snapshot.publish(&meter.reading());
+                        transport.set_bpm(engine.orchestrator.bpm());
+                        transport.set_seconds(engine.orchestrator.clock().seconds());
+                        transport.set_output_latency(
+                            (scheduler.latency() + resampler.delay()).as_secs_f64(),
+                        );
+                        if transport.is_playing() != engine.orchestrator.is_performing() {
+                            transport.set_is_playing(engine.orchestrator.is_performing());
+
+                            // The control bar decides whether the device
+                            // should keep running, so let it know promptly.
+                            ctx.request_repaint();
+                        }
+                    }
+                    stream::AudioInterfaceEvent::Underrun(when, frames) => {
+                        underruns.record(when, frames);
+                        ctx.request_repaint();
+                    }
+                    stream::AudioInterfaceEvent::Error(_)
+                    | stream::AudioInterfaceEvent::Recovering(_)
+                    | stream::AudioInterfaceEvent::DeviceState(_)
+                    | stream::AudioInterfaceEvent::OutputChannels(_) => {
+                        // The UI reports these, so let it know promptly.
+                        ctx.request_repaint();
+                    }
+                    stream::AudioInterfaceEvent::Quit => break,
+                }
+            }
+            audio_stream_service.shutdown(AudioStreamService::SHUTDOWN_TIMEOUT)
+        })
+    }
+
+    /// Stops every background thread we know about, waiting a bounded time
+    /// for each, and reports anything that went wrong. Input streams stop
+    /// when they're dropped.
+    fn shut_down(&mut self) {
+        let _ = self.audio_stream_sender.send(AudioInterfaceInput::Quit);
+        if let Some(audio_thread) = self.audio_thread.take() {
+            // The audio thread also waits for the service, so allow for both.
+            match stream::join_within(
+                audio_thread,
+                "Audio",
+                AudioStreamService::SHUTDOWN_TIMEOUT * 2,
+            ) {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) | Err(err) => eprintln!("Audio shutdown: {err}"),
+            }
+        }
+        self.render_panel.shut_down();
+    }
+
+    /// Produces exactly `frame_count` frames at the device's rate and pushes
+    /// them onto `queue`. The engine's orchestrator runs at the project's
+    /// rate, and `resampler` converts between the two. Edits waiting in
+    /// `scheduler` are applied between ticks, on the frame they belong on.
+    /// `click` puts a matching frame of metronome onto `cue` for each one. If
+    /// the engine has monitored input, captured frames are converted to the
+    /// project's rate and mixed into the orchestrator's output, so that the
+    /// user can hear what's coming in and the analyzer and meter see it.
+    /// `meter` measures exactly what goes onto the queue, and `tap` gets a
+    /// copy of the orchestrator's output, input included. Anything the
+    /// orchestrator reports goes to `groove_events`.
+    ///
+    /// When the song ends, the orchestrator either starts over or stops,
+    /// depending on `is_looping`.
+    #[allow(clippy::too_many_arguments)]
+    fn generate_audio(
+        engine: &mut AudioEngine,
+        scheduler: &mut Scheduler<AudioEngine>,
+        resampler: &mut Resampler,
+        meter: &mut Meter,
+        click: &mut Click,
+        tap: &AnalyzerTap,
+        queue: &AudioQueue,
+        cue: &AudioQueue,
+        groove_events: &Sender<GrooveEvent>,
+        is_looping: bool,
+        frame_count: usize,
+    ) -> Generated {
+        let mut generated = Generated::default();
+        let mut project_buffer = [StereoSample::SILENCE; SAMPLE_BUFFER_SIZE];
+        let mut input_buffer = [StereoSample::SILENCE; SAMPLE_BUFFER_SIZE];
+        let mut buffer = [StereoSample::SILENCE; SAMPLE_BUFFER_SIZE];
+        let mut cue_buffer = [StereoSample::SILENCE; SAMPLE_BUFFER_SIZE];
+        let mut remaining = frame_count;
+        while remaining > 0 {
+            // Stop short of the next edit so that it lands on its own frame.
+            // Nothing is due on the frame we're about to generate once
+            // apply_due() returns, so each batch has at least one frame.
+            scheduler.apply_due(engine);
+            let AudioEngine {
+                orchestrator,
+                monitored_input,
+            } = engine;
+            let len = scheduler
+                .frames_until_next_edit()
+                .map_or(remaining, |frames| frames.min(remaining))
+                .min(SAMPLE_BUFFER_SIZE);
+
+            // Where the song is as this batch starts, for the click.
+            let beats_per_second = orchestrator.bpm() / 60.0;
+            let beat = orchestrator
+                .is_performing()
+                .then(|| orchestrator.clock().seconds() * beats_per_second);
+
+            let mut needed = resampler.input_frames_needed(len);
+            while needed > 0 {
+                let project_samples = &mut project_buffer[..needed.min(SAMPLE_BUFFER_SIZE)];
+                Self::tick(
+                    orchestrator,
+                    project_samples,
+                    groove_events,
+                    is_looping,
+                    &mut generated,
+                );
+                if let Some(input) = monitored_input.as_mut() {
+                    input.set_to_rate(resampler.from_rate());
+                    let input_samples = &mut input_buffer[..project_samples.len()];
+                    input.pull(input_samples);
+                    for (sample, captured) in project_samples.iter_mut().zip(input_samples.iter()) {
+                        sample.0 .0 += captured.0 .0;
+                        sample.1 .0 += captured.1 .0;
+                    }
+                }
+                tap.push(project_samples);
+                resampler.push(project_samples);
+                needed -= project_samples.len();
+            }
+            let samples = &mut buffer[..len];
+            resampler.pull(samples);
+
+            meter.process(samples);
+            for sample in samples.iter() {
+                let _ = queue.push(*sample);
+            }
+            let cue_samples = &mut cue_buffer[..len];
+            click.fill(
+                cue_samples,
+                beat,
+                beats_per_second / resampler.to_rate() as f64,
+            );
+            for sample in cue_samples.iter() {
+                let _ = cue.push(*sample);
+            }
+            scheduler.advance(len);
+            remaining -= len;
+        }
+        generated
+    }
+
+    /// Fills `samples` from the orchestrator, dealing with the end of the
+    /// song as [AudioPrototype2::generate_audio()] describes.
+    fn tick(
+        orchestrator: &mut Orchestrator,
+        samples: &mut [StereoSample],
+        groove_events: &Sender<GrooveEvent>,
+        is_looping: bool,
+        generated: &mut Generated,
+    ) {
+        let was_playing = orchestrator.is_performing();
+
+        // Anything the orchestrator doesn't fill in should be silent, not
+        // left over from the previous batch.
+        samples.fill(StereoSample::SILENCE);
+        let (response, ticks_completed) = orchestrator.tick(samples);
+        generated.has_events |= Self::forward_response(response, groove_events);
+        if was_playing && ticks_completed < samples.len() {
+            if is_looping {
+                orchestrator.skip_to_start();
+                let (response, _) = orchestrator.tick(&mut samples[ticks_completed..]);
+                generated.has_events |= Self::forward_response(response, groove_events);
+            } else {
+                orchestrator.stop();
+                generated.reached_end = true;
+            }
+        }
+    }
+
+    /// Sends the events in `response` to the UI. Returns true if there were
+    /// any.
+    fn forward_response(
+        response: groove_orchestration::messages::Response<GrooveEvent>,
+        groove_events: &Sender<GrooveEvent>,
+    ) -> bool {
+        match response.0 {
+            groove_orchestration::messages::Internal::None => false,
+            groove_orchestration::messages::Internal::Single(event) => {
+                let _ = groove_events.send(event);
+                true
+            }
+            groove_orchestration::messages::Internal::Batch(events) => {
+                let has_events = !events.is_empty();
+                for event in events {
+                    let _ = groove_events.send(event);
+                }
+                has_events
+            }
+        }
+    }
+
+    /// Acts on whatever the audio thread's orchestrator has reported since the
+    /// last frame, and adds it to the event log.
+    fn handle_groove_events(&mut self) {
+        for event in self.groove_events.try_iter() {
+            let text = match &event {
+                GrooveEvent::EntityMessage(uid, message) => {
+                    let name = self
+                        .orchestrator
+                        .entity_iter()
+                        .find(|(entity_uid, _)| **entity_uid == *uid)
+                        .map_or("(unknown)".to_string(), |(_, entity)| {
+                            entity.as_has_uid().name().to_string()
+                        });
+                    format!("{name} (#{uid}): {message:?}")
+                }
+                GrooveEvent::MidiToExternal(channel, message) => {
+                    // We don't have a MIDI output port yet, so the log is the
+                    // only place these go.
+                    format!("MIDI out, channel {channel}: {message:?}")
+                }
+                GrooveEvent::OutputComplete => {
+                    // The audio thread has already started the song over or
+                    // stopped it, depending on whether it's looping. Telling
+                    // it to stop would cut a loop short, so only our copy
+                    // needs to catch up.
+                    if !self.transport.is_looping() {
+                        self.orchestrator.stop();
+                    }
+                    "End of song".to_string()
+                }
+                event => format!("{event:?}"),
+            };
+            self.event_log.push(text);
+        }
+    }
+
+    /// Where instantiated projects look for samples and other assets.
+    const ASSETS_PATH: &str = "/home/miket/src/groove/assets";
+
+    /// Sends `edits` to the audio thread, and notes them against the loaded
+    /// song so that other copies of it can catch up.
+    fn send_edits(&mut self, edits: &mut EditRecorder<Orchestrator>) {
+        for edit in edits.take() {
+            if let Some(song) = self.loaded_song.as_mut() {
+                song.edits.record(&edit);
+            }
+            let TimedEdit { when, edit } = edit;
+            self.edit_sender.send(TimedEdit {
+                when,
+                edit: Arc::new(move |engine: &mut AudioEngine| edit(&mut engine.orchestrator)),
+            });
+        }
+    }
+
+    /// Passes along whatever the audio thread heard from the audio stream
+    /// service.
+    fn handle_stream_events(&mut self) {
+        while let Some(event) = self.stream_events.pop() {
+            self.stats.handle_event(&event);
+            match event {
+                AudioInterfaceEvent::Reset(sample_rate, queue, _) => {
+                    let project_sample_rate = self.transport.project_sample_rate();
+                    self.status.info(format!(
+                        "Audio stream ready: {} Hz, buffer size {}{}",
+                        sample_rate,
+                        queue.capacity(),
+                        if project_sample_rate == sample_rate {
+                            String::default()
+                        } else {
+                            format!(", converting from {project_sample_rate} Hz")
+                        }
+                    ));
+                }
+                AudioInterfaceEvent::Error(err) => self.status.error(err.to_string()),
+                AudioInterfaceEvent::Recovering(attempt) => self.status.info(format!(
+                    "Trying to reconnect to audio device (attempt {attempt})"
+                )),
+                _ => {}
+            }
+        }
+    }
+
+    /// Replaces both copies of the project. `edits` are what's been done to
+    /// the old one so far this frame; they're sent first, so that none of
+    /// them land on the new one.
+    fn handle_load(&mut self, edits: &mut EditRecorder<Orchestrator>) {
+        self.send_edits(edits);
+
+        let path = PathBuf::from(
+            "/home/miket/src/groove/projects/demos/controllers/stereo-automation.yaml",
+        );
+        // One instance for the UI and one for the audio thread.
+        let assets_path = Path::new(Self::ASSETS_PATH);
+        match render::project_sample_rate(&path).and_then(|sample_rate| {
+            Ok((
+                sample_rate,
+                render::load_song(&path, assets_path)?,
+                render::load_song(&path, assets_path)?,
+            ))
+        }) {
+            Ok((sample_rate, ui_copy, mut audio_copy)) => {
+                self.orchestrator = ui_copy;
+                self.bpm = self.orchestrator.bpm();
+                audio_copy.reset(sample_rate);
+
+                // The rate changes at the same moment as the project, so the
+                // audio thread never plays one at the other's rate. Edits to
+                // the old project that are still waiting for their moment
+                // apply first, to the old project. Edits can run more than
+                // once, but there's only one project to hand over, so it's
+                // taken out of the cell the first time.
+                let audio_copy = AtomicCell::new(Some(Box::new(audio_copy)));
+                let transport = Arc::clone(&self.transport);
+                self.edit_sender.send(TimedEdit::immediate(Arc::new(
+                    move |engine: &mut AudioEngine| {
+                        if let Some(audio_copy) = audio_copy.take() {
+                            engine.orchestrator = *audio_copy;
+                            transport.set_project_sample_rate(sample_rate);
+                        }
+                    },
+                )));
+                self.render_panel.use_project_sample_rate(sample_rate);
+                self.loaded_song = Some(LoadedSong {
+                    path,
+                    sample_rate,
+                    edits: EditLog::default(),
+                });
+            }
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+
+    /// The rates the app offers, for projects and for renders.
+    const SAMPLE_RATES: [usize; 4] = [44100, 48000, 88200, 96000];
+
+    /// Lets the user choose the rate the project runs at.
+    fn show_project_sample_rate(&mut self, ui: &mut egui::Ui) {
+        let current = self.transport.project_sample_rate();
+        let mut sample_rate = current;
+        ComboBox::new("project-sample-rate", "Project rate")
+            .selected_text(format!("{current} Hz"))
+            .show_ui(ui, |ui| {
+                for rate in Self::SAMPLE_RATES {
+                    ui.selectable_value(&mut sample_rate, rate, format!("{rate} Hz"));
+                }
+            })
+            .response
+            .on_hover_text("Changing the rate starts the song over");
+        if sample_rate != current {
+            self.set_project_sample_rate(sample_rate);
+        }
+    }
+
+    /// Runs the project at `sample_rate` from now on. The audio thread's copy
+    /// is reset to the new rate at the same moment its output starts being
+    /// converted from it, which takes the song back to the top.
+    fn set_project_sample_rate(&mut self, sample_rate: usize) {
+        let transport = Arc::clone(&self.transport);
+        self.edit_sender.send(TimedEdit::immediate(Arc::new(
+            move |engine: &mut AudioEngine| {
+                engine.orchestrator.reset(sample_rate);
+                transport.set_project_sample_rate(sample_rate);
+            },
+        )));
+        self.render_panel.use_project_sample_rate(sample_rate);
+        if let Some(song) = self.loaded_song.as_mut() {
+            song.sample_rate = sample_rate;
+        }
+    }
+}
+
+/// Shows an oscilloscope and a spectrum analyzer for the master output.
+#[derive(Debug)]
+struct AnalyzerPanel {
+    tap: Arc<AnalyzerTap>,
+
+    // The most recent frames from the tap, oldest first.
+    history: VecDeque<StereoSample>,
+    spectrum: Spectrum,
+}
+impl AnalyzerPanel {
+    /// How many frames the tap can hold between UI frames. Enough for a slow
+    /// UI frame at the highest rates we support.
+    const TAP_CAPACITY: usize = 16384;
+
+    /// How many frames the scope shows.
+    const SCOPE_FRAMES: usize = 1024;
+
+    /// How many frames go into each spectrum.
+    const FFT_LEN: usize = 4096;
+
+    /// The quietest level the spectrum shows, in dB.
+    const SPECTRUM_FLOOR_DB: f64 = -100.0;
+
+    fn new(tap: Arc<AnalyzerTap>) -> Self {
+        Self {
+            tap,
+            history: VecDeque::with_capacity(Self::FFT_LEN),
+            spectrum: Spectrum::new(Self::FFT_LEN),
+        }
+    }
+
+    /// How often to look for more frames when nothing's playing. Monitored
+    /// input can still come through then, but there's no need to redraw at
+    /// full speed for it.
+    const IDLE_REFRESH: Duration = Duration::from_millis(250);
+
+    fn show(&mut self, ui: &mut egui::Ui, is_playing: bool, sample_rate: usize) {
+        let new_frames = self.tap.drain_into(&mut self.history, Self::FFT_LEN);
+
+        // Watching a single entity isn't possible yet. See AnalyzerTap.
+        ui.label("Master output")
+            .on_hover_text("Per-entity analysis isn't available yet");
+
+        let scope_start = self.history.len().saturating_sub(Self::SCOPE_FRAMES);
+        let scope_line = |channel: usize| {
+            let points: PlotPoints = self
+                .history
+                .range(scope_start..)
+                .enumerate()
+                .map(|(i, frame)| {
+                    let value = if channel == 0 { frame.0 .0 } else { frame.1 .0 };
+                    [i as f64, value]
+                })
+                .collect();
+            Line::new(points).name(if channel == 0 { "left" } else { "right" })
+        };
+        Plot::new("analyzer-scope")
+            .height(100.0)
+            .include_y(-1.0)
+            .include_y(1.0)
+            .allow_drag(false)
+            .allow_zoom(false)
+            .allow_scroll(false)
+            .show(ui, |plot_ui| {
+                plot_ui.line(scope_line(0));
+                plot_ui.line(scope_line(1));
+            });
+
+        // Analyze the mono sum, and plot it against a log frequency axis.
+        let levels = self.spectrum.analyze(
+            self.history
+                .iter()
+                .map(|frame| (frame.0 .0 + frame.1 .0) / 2.0),
+        );
+        let hz_per_bin = sample_rate as f64 / Self::FFT_LEN as f64;
+        let points: PlotPoints = levels
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(bin, level)| {
+                [
+                    (bin as f64 * hz_per_bin).log10(),
+                    level.max(Self::SPECTRUM_FLOOR_DB),
+                ]
+            })
+            .collect();
+        Plot::new("analyzer-spectrum")
+            .height(100.0)
+            .include_y(Self::SPECTRUM_FLOOR_DB)
+            .include_y(0.0)
+            .allow_drag(false)
+            .allow_zoom(false)
+            .allow_scroll(false)
+            .x_axis_formatter(|x, _range| format!("{:0.0}", 10.0f64.powf(x)))
+            .label_formatter(|_name, point| {
+                format!("{:0.0} Hz\n{:0.1} dB", 10.0f64.powf(point.x), point.y)
+            })
+            .show(ui, |plot_ui| plot_ui.line(Line::new(points)));
+
+        // Keep the display moving while the panel is open and there's
+        // something to see.
+        let is_silent = self
+            .history
+            .iter()
+            .rev()
+            .take(new_frames)
+            .all(|frame| frame.0 .0 == 0.0 && frame.1 .0 == 0.0);
+        if is_playing || !is_silent {
+            ui.ctx().request_repaint();
+        } else {
+            ui.ctx().request_repaint_after(Self::IDLE_REFRESH);
+        }
+    }
+}
+
+/// Shows how hard the audio thread is working, and can benchmark each entity
+/// on its own to find the likely expensive ones.
+#[derive(Debug, Default)]
+struct PerformancePanel {
+    job: Option<JoinHandle<anyhow::Result<Vec<EntityCost>>>>,
+    costs: Vec<EntityCost>,
+    status: Option<String>,
+}
+impl PerformancePanel {
+    /// How often to refresh the load display.
+    const REFRESH: Duration = Duration::from_millis(250);
+
+    /// How much audio each entity processes while it's being profiled.
+    const PROFILE_DURATION: Duration = Duration::from_secs(1);
+
+    /// `position` is where the song is now, in seconds, which is where the
+    /// profile is taken.
+    fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        cpu_load: &CpuLoad,
+        song: Option<&LoadedSong>,
+        position: f64,
+        assets_path: &Path,
+    ) {
+        if let Some(job) = self.job.as_ref() {
+            if job.is_finished() {
+                self.finish_job();
+            }
+        }
+
+        ui.horizontal(|ui| {
+            let load = cpu_load.load();
+            ui.add(
+                egui::ProgressBar::new(load.min(1.0) as f32)
+                    .desired_width(120.0)
+                    .text(format!("{:0.0}%", load * 100.0)),
+            )
+            .on_hover_text("Time spent generating audio, as a share of the time available");
+            ui.label(format!("peak {:0.0}%", cpu_load.peak() * 100.0));
+            if ui.button("reset").clicked() {
+                cpu_load.reset_peak();
+            }
+        });
+        ui.ctx().request_repaint_after(Self::REFRESH);
+
+        ui.horizontal(|ui| {
+            if self.job.is_some() {
+                ui.spinner();
+                ui.label("benchmarking...");
+                ui.ctx().request_repaint();
+            } else if ui
+                .add_enabled(song.is_some(), egui::Button::new("benchmark entities"))
+                .on_hover_text(
+                    "A synthetic benchmark, not a measurement of playback. Plays a separate \
+                    copy of the project up to the current position, then runs each entity \
+                    on its own from there",
+                )
+                .clicked()
+            {
+                if let Some(song) = song {
+                    self.start_job(song.clone(), position, assets_path.to_path_buf());
+                }
+            }
+        });
+        if let Some(status) = self.status.as_ref() {
+            ui.label(status);
+        }
+        if !self.costs.is_empty() {
+            ui.label("Synthetic benchmark, each entity run alone")
+                .on_hover_text("These aren't timed on the audio thread during playback");
+            egui::Grid::new("entity-costs")
+                .striped(true)
+                .show(ui, |ui| {
+                    for cost in self.costs.iter() {
+                        ui.label(format!("{} (#{})", cost.name, cost.uid));
+                        ui.add(
+                            egui::ProgressBar::new(cost.load.min(1.0) as f32)
+                                .desired_width(80.0)
+                                .text(format!("{:0.1}%", cost.load * 100.0)),
+                        );
+                        ui.end_row();
+                    }
+                });
+        }
+    }
+
+    /// Loads a private copy of `song`, edits and all, and plays it up to
+    /// `position` seconds in, so that the instruments have the notes they'd
+    /// have on the audio thread and the effects have real audio to work on.
+    /// Then times each of its entities from there.
+    fn start_job(&mut self, song: LoadedSong, position: f64, assets_path: PathBuf) {
+        self.status = None;
+        self.job = Some(std::thread::spawn(move || {
+            let mut orchestrator = song.instantiate(&assets_path)?;
+            let sample_rate = song.sample_rate;
+            orchestrator.reset(sample_rate);
+            let input = Self::play_to(&mut orchestrator, position);
+            let frames =
+                (Self::PROFILE_DURATION.as_secs_f64() * sample_rate as f64).round() as usize;
+            let entities: Vec<(usize, String)> = orchestrator
+                .entity_iter()
+                .map(|(uid, entity)| (*uid, entity.as_has_uid().name().to_string()))
+                .collect();
+            Ok(profile::profile_entities(
+                entities,
+                Self::PROFILE_DURATION,
+                |uid| {
+                    orchestrator
+                        .get_mut(uid)
+                        .is_some_and(|entity| Self::run_entity(entity, frames, &input))
+                },
+            ))
+        }));
+    }
+
+    fn finish_job(&mut self) {
+        if let Some(job) = self.job.take() {
+            match job.join() {
+                Ok(Ok(costs)) => {
+                    self.status = Some(format!(
+                        "{:0.1}% of the budget in total",
+                        costs.iter().map(|cost| cost.load).sum::<f64>() * 100.0
+                    ));
+                    self.costs = costs;
+                }
+                Ok(Err(err)) => self.status = Some(format!("Profiling failed: {err}")),
+                Err(_) => self.status = Some("Profiling thread panicked".to_string()),
+            }
+        }
+    }
+
+    /// Plays `orchestrator` from the top until its clock reaches `position`
+    /// seconds, or the song ends, but for at least one buffer. Returns the last
+    /// buffer it produced, for effects to chew on.
+    fn play_to(orchestrator: &mut Orchestrator, position: f64) -> Vec<StereoSample> {
+        let mut buffer = [StereoSample::SILENCE; SAMPLE_BUFFER_SIZE];
+        orchestrator.skip_to_start();
+        orchestrator.play();
+        loop {
+            buffer.fill(StereoSample::SILENCE);
+            let (_, ticks_completed) = orchestrator.tick(&mut buffer);
+            if ticks_completed < buffer.len() || orchestrator.clock().seconds() >= position {
+                break;
+            }
+        }
+        buffer.to_vec()
+    }
+
+    /// Makes `entity` process `frames` frames the way the orchestrator would:
+    /// instruments generate, effects transform `input` over and over, and
+    /// controllers tick. Returns false if the entity is none of those.
+    fn run_entity(
+        entity: &mut groove_orchestration::Entity,
+        frames: usize,
+        input: &[StereoSample],
+    ) -> bool {
+        if let Some(instrument) = entity.as_is_instrument_mut() {
+            for _ in 0..frames {
+                instrument.tick(1);
+                std::hint::black_box(instrument.value());
+            }
+        } else if let Some(effect) = entity.as_is_effect_mut() {
+            for sample in input.iter().cycle().take(frames) {
+                std::hint::black_box(effect.transform_audio(*sample));
+            }
+        } else if let Some(controller) = entity.as_is_controller_mut() {
+            for _ in 0..frames {
+                std::hint::black_box(controller.tick(1));
+            }
+        } else {
+            return false;
+        }
+        true
+    }
+}
+
+/// What happened during a call to [AudioPrototype2::generate_audio()].
+#[derive(Debug, Default)]
+struct Generated {
+    // The orchestrator had something to say.
+    has_events: bool,
+
+    // The song ended and the orchestrator stopped.
+    reached_end: bool,
+}
+
+/// The transport controls. Besides playing and stopping the orchestrator, it
+/// decides whether the audio device should be running: always, if the user
+/// wants instant starts, and otherwise only while the song is playing.
+#[derive(Debug)]
+struct ControlBar {
+    keep_device_running: bool,
+
+    // Set when the user presses play, until the audio thread confirms that
+    // the orchestrator is playing. Until then the device has to keep running,
+    // or the audio thread would never get to the play.
+    is_starting: bool,
+
+    // What we last asked the device to do. The service starts out running.
+    device_should_run: bool,
+}
+impl Default for ControlBar {
+    fn default() -> Self {
+        Self {
+            keep_device_running: false,
+            is_starting: false,
+            device_should_run: true,
+        }
+    }
+}
+impl ControlBar {
+    fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        orchestrator: &mut Orchestrator,
+        edits: &mut EditRecorder<Orchestrator>,
+        transport: &TransportSnapshot,
+        meter: &MeterSnapshot,
+        audio_stream_sender: &Sender<AudioInterfaceInput>,
+        device_state: AudioDeviceState,
+    ) {
+        ui.horizontal(|ui| {
+            let mut bpm = orchestrator.bpm();
+            ui.label("BPM");
+            if ui.add(DragValue::new(&mut bpm).speed(0.1)).changed() {
+                edits.apply(orchestrator, move |o| o.set_bpm(bpm));
+            }
+            if ui.button("start over").clicked() {
+                edits.apply_immediately(orchestrator, |o| o.skip_to_start());
+            }
+
+            // The audio thread stops on its own at the end of the song, so
+            // its idea of whether we're playing is the one that counts.
+            let is_playing = transport.is_playing();
+            if ui
+                .add_enabled(!is_playing, egui::Button::new("play"))
+                .clicked()
+            {
+                edits.apply_immediately(orchestrator, |o| o.play());
+                self.is_starting = true;
+            }
+            if ui
+                .add_enabled(is_playing, egui::Button::new("pause"))
+                .clicked()
+            {
+                edits.apply_immediately(orchestrator, |o| o.stop());
+            }
+            let mut is_looping = transport.is_looping();
+            if ui.checkbox(&mut is_looping, "loop").changed() {
+                transport.set_is_looping(is_looping);
+            }
+            ui.checkbox(&mut self.keep_device_running, "keep device running")
+                .on_hover_text("Starts instantly, but keeps the audio device busy while stopped");
+            self.update_device(is_playing, audio_stream_sender);
+            Self::show_device_state(ui, device_state);
+
+            // Our copy of the orchestrator is told to play and stop along
+            // with the audio thread's, but it's never ticked, so its clock
+            // doesn't move. Show the audio thread's instead.
+            let position = transport.seconds();
+            let minutes: u8 = (position / 60.0).floor() as u8;
+            let seconds = position as usize % 60;
+            let thousandths = (position.fract() * 1000.0) as u16;
+            ui.label(format!("{minutes:03}:{seconds:02}:{thousandths:03}"));
+            Self::show_meter(ui, meter);
+        });
+
+        // Keep the meters moving while there's something to measure.
+        if transport.is_playing() {
+            ui.ctx().request_repaint_after(Self::METER_REFRESH);
+        }
+    }
+
+    /// Starts or pauses the device if it isn't in the state it should be in.
+    fn update_device(
+        &mut self,
+        is_playing: bool,
+        audio_stream_sender: &Sender<AudioInterfaceInput>,
+    ) {
+        if is_playing {
+            self.is_starting = false;
+        }
+        let should_run = self.keep_device_running || is_playing || self.is_starting;
+        if should_run != self.device_should_run {
+            self.device_should_run = should_run;
+            let _ = audio_stream_sender.send(if should_run {
+                AudioInterfaceInput::Play
+            } else {
+                AudioInterfaceInput::Pause
+            });
+        }
+    }
+
+    /// How often the meters update while the song plays.
+    const METER_REFRESH: Duration = Duration::from_millis(33);
+
+    /// The quietest level the meters show, in dBFS.
+    const METER_FLOOR_DB: f64 = -60.0;
+
+    /// Shows a level bar for each channel, the loudness, and a clip indicator
+    /// that the user can click to clear.
+    fn show_meter(ui: &mut egui::Ui, meter: &MeterSnapshot) {
+        let reading = meter.reading();
+        ui.vertical(|ui| {
+            ui.spacing_mut().item_spacing.y = 2.0;
+            for channel in 0..2 {
+                let (rect, response) =
+                    ui.allocate_exact_size(egui::vec2(120.0, 6.0), egui::Sense::hover());
+                let x = |amplitude: f64| {
+                    let db = meter::to_dbfs(amplitude).max(Self::METER_FLOOR_DB);
+                    let fraction = (1.0 - db / Self::METER_FLOOR_DB).clamp(0.0, 1.0);
+                    rect.left() + rect.width() * fraction as f32
+                };
+                let bar_to = |amplitude: f64| {
+                    egui::Rect::from_min_max(rect.min, egui::pos2(x(amplitude), rect.max.y))
+                };
+                let painter = ui.painter();
+                painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+                painter.rect_filled(
+                    bar_to(reading.peak[channel]),
+                    0.0,
+                    egui::Color32::DARK_GREEN,
+                );
+                painter.rect_filled(bar_to(reading.rms[channel]), 0.0, egui::Color32::GREEN);
+                let hold_x = x(reading.peak_hold[channel]);
+                let hold_color = if reading.peak_hold[channel] >= 1.0 {
+                    ui.visuals().error_fg_color
+                } else {
+                    egui::Color32::YELLOW
+                };
+                painter.line_segment(
+                    [
+                        egui::pos2(hold_x, rect.min.y),
+                        egui::pos2(hold_x, rect.max.y),
+                    ],
+                    (1.0, hold_color),
+                );
+                response.on_hover_text(format!(
+                    "{}: peak {:0.1} dBFS, RMS {:0.1} dBFS",
+                    if channel == 0 { "Left" } else { "Right" },
+                    meter::to_dbfs(reading.peak_hold[channel]),
+                    meter::to_dbfs(reading.rms[channel]),
+                ));
+            }
+        });
+
+        let clip_color = if reading.clipped.iter().any(|clipped| *clipped) {
+            ui.visuals().error_fg_color
+        } else {
+            ui.visuals().weak_text_color()
+        };
+        if ui
+            .add(
+                egui::Label::new(RichText::new("CLIP").color(clip_color))
+                    .sense(egui::Sense::click()),
+            )
+            .on_hover_text("Click to clear")
+            .clicked()
+        {
+            meter.request_clear_clips();
+        }
+
+        let lufs = |lufs: f64| {
+            if lufs.is_finite() {
+                format!("{lufs:0.1}")
+            } else {
+                "—".to_string()
+            }
+        };
+        ui.label(format!(
+            "M {} S {} I {} LUFS",
+            lufs(reading.momentary_lufs),
+            lufs(reading.short_term_lufs),
+            lufs(reading.integrated_lufs)
+        ))
+        .on_hover_text("Momentary, short-term and integrated loudness");
+    }
+
+    fn show_device_state(ui: &mut egui::Ui, device_state: AudioDeviceState) {
+        let color = match device_state {
+            AudioDeviceState::Running => egui::Color32::GREEN,
+            AudioDeviceState::Paused => egui::Color32::YELLOW,
+            AudioDeviceState::Disconnected => ui.visuals().error_fg_color,
+        };
+        ui.label(RichText::new("●").color(color))
+            .on_hover_text(format!("Audio device: {device_state}"));
+    }
+}
+
+/// Lets the user choose the audio host and output device, and tells the audio
+/// stream service to switch to it.
+#[derive(Debug, Default)]
+struct DevicePicker {
+    hosts: Vec<HostId>,
+    host: Option<HostId>,
+    devices: Vec<AudioDeviceDescription>,
+    selected: Option<AudioDeviceId>,
+    buffer_size: usize,
+
+    // One entry per channel of the device the service has open, which isn't
+    // necessarily the selected one. Resized whenever the service reports a
+    // different channel count.
+    channel_sources: Vec<ChannelSource>,
+}
+impl DevicePicker {
+    fn new() -> Self {
+        let mut r = Self {
+            buffer_size: AudioStream::REASONABLE_BUFFER_SIZE,
+            ..Default::default()
+        };
+        r.refresh();
+        r
+    }
+
+    /// Enumerating devices can be slow, so we do it only on request rather
+    /// than on every frame.
+    fn refresh(&mut self) {
+        self.hosts = AudioStreamService::hosts();
+        if !matches!(self.host, Some(host) if self.hosts.contains(&host)) {
+            self.host = self.hosts.first().copied();
+        }
+        self.refresh_devices();
+    }
+
+    fn refresh_devices(&mut self) {
+        self.devices = if let Some(host) = self.host {
+            match AudioStreamService::output_devices(host) {
+                Ok(devices) => devices,
+                Err(err) => {
+                    eprintln!("output_devices: {}", err);
+                    Vec::default()
+                }
+            }
+        } else {
+            Vec::default()
+        };
+        if self.selected.is_none() {
+            self.selected = self
+                .devices
+                .iter()
+                .find(|d| d.is_default)
+                .map(|d| d.id.clone());
+        }
+    }
+
+    /// `output_channels` is what the service says the open device has.
+    fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        sender: &Sender<AudioInterfaceInput>,
+        output_channels: Option<usize>,
+    ) {
+        let mut host = self.host;
+        ComboBox::new("audio-host", "Host")
+            .selected_text(host.map_or("(none)", |h| h.name()))
+            .show_ui(ui, |ui| {
+                for h in self.hosts.iter() {
+                    ui.selectable_value(&mut host, Some(*h), h.name());
+                }
+            });
+        if host != self.host {
+            self.host = host;
+            self.refresh_devices();
+        }
+
+        let mut selected = self.selected.clone();
+        ComboBox::new("audio-device", "Device")
+            .selected_text(selected.as_ref().map_or("(none)", |d| d.name.as_str()))
+            .show_ui(ui, |ui| {
+                for d in self.devices.iter() {
+                    let label = if d.is_default {
+                        format!("{} (default)", d.id.name)
+                    } else {
+                        d.id.name.clone()
+                    };
+                    ui.selectable_value(&mut selected, Some(d.id.clone()), label);
+                }
+            });
+        self.selected = selected;
+
+        if let Some(description) = self
+            .devices
+            .iter()
+            .find(|d| Some(&d.id) == self.selected.as_ref())
+        {
+            if let Some(config) = description.default_config.as_ref() {
+                ui.label(format!(
+                    "Default: {} ch, {} Hz, {}",
+                    config.channels(),
+                    config.sample_rate().0,
+                    config.sample_format()
+                ));
+            }
+            CollapsingHeader::new("Supported configs")
+                .default_open(false)
+                .show(ui, |ui| {
+                    for range in description.supported_configs.iter() {
+                        ui.label(format!(
+                            "{} ch, {}-{} Hz, {}",
+                            range.channels(),
+                            range.min_sample_rate().0,
+                            range.max_sample_rate().0,
+                            range.sample_format()
+                        ));
+                    }
+                });
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("refresh").clicked() {
+                self.refresh();
+            }
+            if ui
+                .add_enabled(self.selected.is_some(), egui::Button::new("use"))
+                .clicked()
+            {
+                if let Some(device_id) = self.selected.clone() {
+                    let _ = sender.send(AudioInterfaceInput::SetDevice(device_id));
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Buffer size");
+            ui.add(DragValue::new(&mut self.buffer_size).clamp_range(64..=16384));
+            if ui.button("apply").clicked() {
+                let _ = sender.send(AudioInterfaceInput::SetBufferSize(self.buffer_size));
+            }
+        });
+
+        CollapsingHeader::new("Channel map")
+            .default_open(false)
+            .show(ui, |ui| self.show_channel_map(ui, sender, output_channels));
+    }
+
+    /// The map applies to whatever device is open, so it's sized from that
+    /// rather than from the selection, which may not have been used yet.
+    fn show_channel_map(
+        &mut self,
+        ui: &mut egui::Ui,
+        sender: &Sender<AudioInterfaceInput>,
+        output_channels: Option<usize>,
+    ) {
+        let Some(channel_count) = output_channels else {
+            ui.label("No output device is open");
+            return;
+        };
+        if self.channel_sources.len() != channel_count {
+            self.channel_sources = ChannelMap::default_for(channel_count).sources().to_vec();
+        }
+
+        for (i, source) in self.channel_sources.iter_mut().enumerate() {
+            ComboBox::new(format!("channel-source-{i}"), format!("Output {}", i + 1))
+                .selected_text(source.to_string())
+                .show_ui(ui, |ui| {
+                    for s in ChannelSource::iter() {
+                        ui.selectable_value(source, s, s.to_string());
+                    }
+                });
+        }
+        ui.horizontal(|ui| {
+            if ui.button("reset").clicked() {
+                self.channel_sources = ChannelMap::default_for(channel_count).sources().to_vec();
+            }
+            if ui.button("apply").clicked() {
+                let _ = sender.send(AudioInterfaceInput::SetChannelMap(ChannelMap::new(
+                    self.channel_sources.clone(),
+                )));
+            }
+        });
+    }
+}
+
+/// Captures audio from an input device or a WAV file, and optionally plays it
+/// through the output so the user can hear it. That's all it can do for now:
+/// the input never reaches the orchestrator, so it can't be recorded or sent
+/// through the project's effects.
+struct InputPanel {
+    input: Option<Box<dyn AudioInput>>,
+    sender: Sender<AudioInputEvent>,
+    receiver: Receiver<AudioInputEvent>,
+
+    // The WAV file to use instead of a device.
+    file_path: String,
+
+    // Whether captured frames are mixed into the output.
+    is_monitoring: bool,
+
+    overrun_frames: usize,
+}
+impl Default for InputPanel {
+    fn default() -> Self {
+        let (sender, receiver) = unbounded();
+        Self {
+            input: None,
+            sender,
+            receiver,
+            file_path: Default::default(),
+            is_monitoring: false,
+            overrun_frames: 0,
+        }
+    }
+}
+impl InputPanel {
+    /// Changes to what the audio thread mixes into the output go to `edits`.
+    fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        status: &mut StatusLog,
+        edits: &mut EditRecorder<AudioEngine>,
+    ) {
+        for event in self.receiver.try_iter() {
+            match event {
+                AudioInputEvent::Captured(..) => {}
+                AudioInputEvent::Overrun(_, frames) => self.overrun_frames += frames,
+                AudioInputEvent::Error(err) => status.error(err.to_string()),
+                AudioInputEvent::EndOfInput => status.info("Input file finished".to_string()),
+            }
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("capture").clicked() {
+                match AudioInputStream::create_stream(
+                    None,
+                    AudioStream::REASONABLE_BUFFER_SIZE,
+                    self.sender.clone(),
+                ) {
+                    Ok(input) => self.set_input(Some(Box::new(input)), edits),
+                    Err(err) => status.error(err.to_string()),
+                }
+            }
+            if ui
+                .add_enabled(self.input.is_some(), egui::Button::new("stop"))
+                .clicked()
+            {
+                self.set_input(None, edits);
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.file_path);
+            if ui
+                .add_enabled(!self.file_path.is_empty(), egui::Button::new("use file"))
+                .clicked()
+            {
+                match FileAudioInput::new_with(
+                    Path::new(&self.file_path),
+                    AudioStream::REASONABLE_BUFFER_SIZE,
+                    NullPacing::RealTime,
+                    self.sender.clone(),
+                ) {
+                    Ok(input) => self.set_input(Some(Box::new(input)), edits),
+                    Err(err) => status.error(format!("Couldn't open input file: {err}")),
+                }
+            }
+        });
+        if ui
+            .checkbox(&mut self.is_monitoring, "monitor")
+            .on_hover_text("Mixes the input into the output. It bypasses the project's effects.")
+            .changed()
+        {
+            self.update_monitored(edits);
+        }
+
+        if let Some(input) = self.input.as_ref() {
+            ui.label(format!(
+                "{} Hz, {} frames buffered, {} dropped",
+                input.sample_rate(),
+                input.queue().len(),
+                self.overrun_frames
+            ));
+            ui.ctx().request_repaint();
+        }
+    }
+
+    fn set_input(
+        &mut self,
+        input: Option<Box<dyn AudioInput>>,
+        edits: &mut EditRecorder<AudioEngine>,
+    ) {
+        self.input = input;
+        self.overrun_frames = 0;
+        self.update_monitored(edits);
+    }
+
+    /// Hands the audio thread a feed of the current input if the user is
+    /// monitoring it, or takes its feed away if not. The audio thread
+    /// converts the feed to the project's rate, whatever it's created with.
+    fn update_monitored(&mut self, edits: &mut EditRecorder<AudioEngine>) {
+        let feed = if self.is_monitoring {
+            self.input.as_ref().map(|i| {
+                Box::new(InputFeed::new(
+                    Arc::clone(i.queue()),
+                    i.sample_rate(),
+                    i.sample_rate(),
+                ))
+            })
+        } else {
+            None
+        };
+
+        // Edits can run more than once, but only the first run gets the feed.
+        // Later runs find the cell empty and leave the audio thread's feed
+        // alone.
+        let feed = AtomicCell::new(Some(feed));
+        edits.push(TimedEdit::immediate(Arc::new(
+            move |engine: &mut AudioEngine| {
+                if let Some(feed) = feed.take() {
+                    engine.monitored_input = feed.map(|feed| *feed);
+                }
+            },
+        )));
+    }
+}
 
-    tree: Tree,
+/// The most recent events from the orchestrator, oldest first.
+#[derive(Debug, Default)]
+struct EventLog {
+    events: VecDeque<(Instant, String)>,
 }
-impl Default for AudioPrototype2 {
-    fn default() -> Self {
-        let clock_settings = ClockNano::default();
-        let audio_stream_service = AudioStreamService::new();
-        let audio_stream_sender = audio_stream_service.sender().clone();
-        let orchestrator = Arc::new(Mutex::new(Orchestrator::new_with(clock_settings)));
-        let orchestrator_clone = Arc::clone(&orchestrator);
-        const SAMPLE_RATE: usize = 44100;
-        let sample_rate = Arc::new(Mutex::new(SAMPLE_RATE));
-        Self::start_audio_stream(
-            orchestrator_clone,
-            audio_stream_service,
-            Arc::clone(&sample_rate),
-        );
-        Self {
-            bpm: Default::default(),
-            orchestrator,
-            name: "Arthur".to_owned(),
+impl EventLog {
+    const MAX_EVENTS: usize = 200;
 
-            sample_rate,
-            audio_stream_sender,
-            control_bar: ControlBar::default(),
-            tree: Tree::demo(),
+    fn push(&mut self, text: String) {
+        if self.events.len() == Self::MAX_EVENTS {
+            self.events.pop_front();
         }
+        self.events.push_back((Instant::now(), text));
     }
-}
 
-impl eframe::App for AudioPrototype2 {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        if let Ok(o) = self.orchestrator.lock() {
-            self.bpm = o.bpm();
+    fn show(&mut self, ui: &mut egui::Ui) {
+        if ui.button("clear").clicked() {
+            self.events.clear();
         }
-        let top = egui::TopBottomPanel::top("control-bar");
-        let bottom = egui::TopBottomPanel::bottom("orchestrator");
-        let left = egui::SidePanel::left("left-sidebar");
-        let center = egui::CentralPanel::default();
+        egui::ScrollArea::vertical()
+            .max_height(200.0)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for (when, text) in self.events.iter() {
+                    ui.label(format!(
+                        "{:>6.1}s ago  {text}",
+                        when.elapsed().as_secs_f64()
+                    ));
+                }
+            });
+    }
+}
 
-        top.show(ctx, |ui| {
-            if let Ok(mut o) = self.orchestrator.lock() {
-                self.control_bar.show(ui, &mut o);
-            }
-        });
-        bottom.show(ctx, |ui| {
-            if let Ok(o) = self.orchestrator.lock() {
-                ui.label(format!("clock: {:?}", o.clock()));
-            }
-            if ui.button("load").clicked() {
-                self.handle_load();
-            }
-        });
-        left.show(ctx, |ui| {
-            CollapsingHeader::new("File browser")
-                .default_open(true)
-                .show(ui, |ui| self.tree.ui(ui));
-        });
-        center.show(ctx, |ui| {
-            if let Ok(mut o) = self.orchestrator.lock() {
-                o.show(ui);
-            }
+/// Something that the user should know about the state of the audio stream.
+#[derive(Debug)]
+struct StatusMessage {
+    when: Instant,
+    text: String,
+    is_error: bool,
+}
+
+/// The most recent status messages, oldest first.
+#[derive(Debug, Default)]
+struct StatusLog {
+    messages: VecDeque<StatusMessage>,
+}
+impl StatusLog {
+    const MAX_MESSAGES: usize = 20;
+
+    fn info(&mut self, text: String) {
+        self.push(text, false);
+    }
+
+    fn error(&mut self, text: String) {
+        self.push(text, true);
+    }
+
+    fn push(&mut self, text: String, is_error: bool) {
+        if self.messages.len() == Self::MAX_MESSAGES {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(StatusMessage {
+            when: Instant::now(),
+            text,
+            is_error,
         });
     }
-}
-impl AudioPrototype2 {
-    fn start_audio_stream(
-        orchestrator_clone: Arc<Mutex<Orchestrator>>,
-        audio_stream_service: AudioStreamService,
-        sample_rate_clone: Arc<Mutex<usize>>,
-    ) {
-        std::thread::spawn(move || {
-            let orchestrator = orchestrator_clone;
-            let mut queue_opt = None;
-            loop {
-                if let Ok(event) = audio_stream_service.receiver().recv() {
-                    match event {
-                        stream::AudioInterfaceEvent::Reset(sample_rate, queue) => {
-                            if let Ok(mut sr) = sample_rate_clone.lock() {
-                                *sr = sample_rate;
-                            }
-                            if let Ok(mut o) = orchestrator.lock() {
-                                o.reset(sample_rate);
-                            }
-                            queue_opt = Some(queue);
-                        }
-                        stream::AudioInterfaceEvent::NeedsAudio(_when, count) => {
-                            if let Some(queue) = queue_opt.as_ref() {
-                                if let Ok(o) = orchestrator.lock() {
-                                    Self::generate_audio(o, queue, (count / 64) as u8);
-                                }
-                            }
-                        }
-                        stream::AudioInterfaceEvent::Quit => todo!(),
-                    }
-                }
+
+    /// Shows the latest message, with the rest available on hover.
+    fn show(&self, ui: &mut egui::Ui) {
+        let Some(latest) = self.messages.back() else {
+            ui.label("No audio status yet");
+            return;
+        };
+        ui.label(Self::styled_text(ui, latest)).on_hover_ui(|ui| {
+            for message in self.messages.iter().rev() {
+                ui.label(Self::styled_text(ui, message));
             }
         });
     }
 
-    fn generate_audio(
-        mut orchestrator: MutexGuard<Orchestrator>,
-        queue: &AudioQueue,
-        buffer_count: u8,
-    ) {
-        let mut samples = [StereoSample::SILENCE; SAMPLE_BUFFER_SIZE];
-        for i in 0..buffer_count {
-            let (response, ticks_completed) = orchestrator.tick(&mut samples);
-            if ticks_completed < samples.len() {
-                // self.stop_playback();
-                // self.reached_end_of_playback = true;
-            }
+    fn styled_text(ui: &egui::Ui, message: &StatusMessage) -> RichText {
+        let text = RichText::new(format!(
+            "{} ({}s ago)",
+            message.text,
+            message.when.elapsed().as_secs()
+        ));
+        if message.is_error {
+            text.color(ui.visuals().error_fg_color)
+        } else {
+            text
+        }
+    }
+}
+
+/// A render running on a background thread.
+struct RenderJob {
+    settings: RenderSettings,
+    progress: Arc<RenderProgress>,
+    handler: JoinHandle<anyhow::Result<RenderOutcome>>,
+}
+
+/// Bounces the loaded project to a WAV file, showing progress while it works.
+#[derive(Default)]
+struct RenderPanel {
+    settings: RenderSettings,
+    job: Option<RenderJob>,
+    status: Option<String>,
+}
+impl RenderPanel {
+    /// Writes files at the project's rate, `sample_rate`, unless the user
+    /// picks another.
+    fn use_project_sample_rate(&mut self, sample_rate: usize) {
+        self.settings.sample_rate = sample_rate;
+    }
 
-            for sample in samples {
-                let _ = queue.push(sample);
+    fn show(&mut self, ui: &mut egui::Ui, song: Option<&LoadedSong>, assets_path: &Path) {
+        if let Some(job) = self.job.as_ref() {
+            if job.handler.is_finished() {
+                self.finish_job();
             }
+        }
 
-            match response.0 {
-                groove_orchestration::messages::Internal::None => {}
-                groove_orchestration::messages::Internal::Single(event) => {
-                    //                    self.handle_groove_event(event);
+        ui.horizontal(|ui| {
+            if let Some(job) = self.job.as_ref() {
+                // The song doesn't know how long it is until it's over, so
+                // measure progress against the length limit. A song that
+                // ends early jumps the bar to done.
+                let frames_rendered = job.progress.frames_rendered();
+                let seconds = frames_rendered as f64 / job.settings.sample_rate as f64;
+                let fraction = frames_rendered as f32 / job.settings.max_frames().max(1) as f32;
+                ui.add(
+                    egui::ProgressBar::new(fraction.min(1.0))
+                        .desired_width(200.0)
+                        .text(format!("{seconds:0.1}s rendered")),
+                );
+                if ui.button("cancel").clicked() {
+                    job.progress.cancel();
                 }
-                groove_orchestration::messages::Internal::Batch(events) => {
-                    for event in events {
-                        //                      self.handle_groove_event(event)
+
+                // Keep the bar moving even if nothing else is happening in
+                // the UI.
+                ui.ctx().request_repaint();
+            } else {
+                self.show_settings(ui);
+                if ui
+                    .add_enabled(song.is_some(), egui::Button::new("render to file"))
+                    .clicked()
+                {
+                    if let Some(song) = song {
+                        self.start_job(song.clone(), assets_path.to_path_buf());
                     }
                 }
             }
-        }
+            if let Some(status) = self.status.as_ref() {
+                ui.label(status);
+            }
+        });
     }
 
-    fn handle_load(&mut self) {
-        let filename = "/home/miket/src/groove/projects/demos/controllers/stereo-automation.yaml";
-        match SongSettings::new_from_yaml_file(filename) {
-            Ok(s) => {
-                let pb = PathBuf::from("/home/miket/src/groove/assets");
-                match s.instantiate(&pb, false) {
-                    Ok(instance) => {
-                        if let Ok(mut o) = self.orchestrator.lock() {
-                            if let Ok(sample_rate) = self.sample_rate.lock() {
-                                *o = instance;
-                                self.bpm = o.bpm();
-                                o.reset(*sample_rate);
-                            }
-                        }
-                    }
-                    Err(err) => eprintln!("instantiate: {}", err),
-                }
-            }
-            Err(err) => eprintln!("new_from_yaml: {}", err),
+    fn show_settings(&mut self, ui: &mut egui::Ui) {
+        let mut path = self.settings.path.to_string_lossy().to_string();
+        if ui.text_edit_singleline(&mut path).changed() {
+            self.settings.path = PathBuf::from(path);
         }
+        ComboBox::new("render-sample-rate", "")
+            .selected_text(format!("{} Hz", self.settings.sample_rate))
+            .show_ui(ui, |ui| {
+                for rate in AudioPrototype2::SAMPLE_RATES {
+                    ui.selectable_value(&mut self.settings.sample_rate, rate, format!("{rate} Hz"));
+                }
+            });
+        ComboBox::new("render-format", "")
+            .selected_text(self.settings.format.to_string())
+            .show_ui(ui, |ui| {
+                for format in WavFormat::iter() {
+                    ui.selectable_value(&mut self.settings.format, format, format.to_string());
+                }
+            });
     }
-}
 
-#[derive(Debug, Default)]
-struct ControlBar {}
-impl ControlBar {
-    fn show(&self, ui: &mut egui::Ui, orchestrator: &mut Orchestrator) {
-        ui.horizontal(|ui| {
-            let mut bpm = orchestrator.bpm();
-            ui.label("BPM");
-            if ui.add(DragValue::new(&mut bpm).speed(0.1)).changed() {
-                orchestrator.set_bpm(bpm);
-            }
-            if ui.button("start over").clicked() {
-                orchestrator.skip_to_start();
-            }
-            if ui.button("play").clicked() {
-                orchestrator.play();
-            }
-            if ui.button("pause").clicked() {
-                orchestrator.stop();
+    /// Renders `song` with all the edits made to it so far. Later edits don't
+    /// affect a render that's already started.
+    fn start_job(&mut self, song: LoadedSong, assets_path: PathBuf) {
+        let settings = RenderSettings {
+            project_sample_rate: song.sample_rate,
+            ..self.settings.clone()
+        };
+        let progress = Arc::new(RenderProgress::default());
+        let handler = {
+            let settings = settings.clone();
+            let progress = Arc::clone(&progress);
+            std::thread::spawn(move || {
+                let mut orchestrator = song.instantiate(&assets_path)?;
+                render::render_to_wav(&mut orchestrator, &settings, &progress)
+            })
+        };
+        self.status = None;
+        self.job = Some(RenderJob {
+            settings,
+            progress,
+            handler,
+        });
+    }
+
+    /// Cancels any render in progress and waits for it to stop.
+    fn shut_down(&mut self) {
+        if let Some(job) = self.job.take() {
+            job.progress.cancel();
+            if let Err(err) =
+                stream::join_within(job.handler, "Render", AudioStreamService::SHUTDOWN_TIMEOUT)
+            {
+                eprintln!("Render shutdown: {err}");
             }
+        }
+    }
 
-            let clock = orchestrator.clock();
-            let minutes: u8 = (clock.seconds() / 60.0).floor() as u8;
-            let seconds = clock.seconds() as usize % 60;
-            let thousandths = (clock.seconds().fract() * 1000.0) as u16;
-            ui.label(format!("{minutes:03}:{seconds:02}:{thousandths:03}"));
-        });
+    fn finish_job(&mut self) {
+        if let Some(job) = self.job.take() {
+            self.status = Some(match job.handler.join() {
+                Ok(Ok(RenderOutcome::Finished(frames))) => format!(
+                    "Rendered {:0.1}s to {}",
+                    frames as f64 / job.settings.sample_rate as f64,
+                    job.settings.path.display()
+                ),
+                Ok(Ok(RenderOutcome::Cancelled)) => "Render cancelled".to_string(),
+                Ok(Err(err)) => format!("Render failed: {err}"),
+                Err(_) => "Render thread panicked".to_string(),
+            });
+        }
     }
 }
 
@@ -232,37 +1902,458 @@ trait Shows {
     fn show(&mut self, ui: &mut egui::Ui);
 }
 
-impl Shows for Envelope {
+/// Like [Shows], but for things that the audio thread has its own copy of.
+/// Every change is made through `edits`, which applies it here and records it
+/// so that it can be replayed there.
+trait ShowsEdits: Sized + 'static {
+    fn show(&mut self, ui: &mut egui::Ui, edits: &mut EditRecorder<Self>);
+}
+
+impl Shows for AudioStats {
     fn show(&mut self, ui: &mut egui::Ui) {
-        let mut attack = self.attack();
-        let mut decay = self.decay();
-        let mut sustain = self.sustain().value();
-        let mut release = self.release();
-        ui.label("Attack");
-        if ui.add(DragValue::new(&mut attack).speed(0.1)).changed() {
-            self.set_attack(attack);
+        ui.label(format!(
+            "Underruns: {} ({} frames)",
+            self.underrun_count(),
+            self.underrun_frames()
+        ));
+        if let Some(last) = self.last_underrun() {
+            ui.label(format!(
+                "Last underrun: {:0.1}s ago",
+                last.elapsed().as_secs_f64()
+            ));
         }
+        if let Some(mean) = self.mean_callback_interval() {
+            ui.label(format!(
+                "Callback interval: {:0.2}ms mean, {:0.2}ms max",
+                mean.as_secs_f64() * 1000.0,
+                self.max_callback_interval().as_secs_f64() * 1000.0
+            ));
+        }
+        ui.label(format!("Queue fill level (capacity {})", self.capacity()));
+        let points: PlotPoints = self
+            .fill_levels()
+            .enumerate()
+            .map(|(i, level)| [i as f64, *level as f64])
+            .collect();
+        Plot::new("queue-fill-level")
+            .height(80.0)
+            .include_y(0.0)
+            .include_y(self.capacity() as f64)
+            .allow_drag(false)
+            .allow_zoom(false)
+            .allow_scroll(false)
+            .show(ui, |plot_ui| plot_ui.line(Line::new(points)));
+        if ui.button("clear").clicked() {
+            self.clear();
+        }
+
+        // Stats change constantly, so keep the panel up to date.
+        ui.ctx().request_repaint();
+    }
+}
+
+/// Shows `show`, the editor for one part of `owner`, which is read with `get`
+/// and written back whole with `set`. Each of the part's edits is recorded as
+/// an edit to `owner`, so that it reaches the same part of the audio thread's
+/// copy.
+fn show_part_with<O: 'static, P: Send + 'static>(
+    ui: &mut egui::Ui,
+    owner: &mut O,
+    edits: &mut EditRecorder<O>,
+    get: fn(&O) -> P,
+    set: fn(&mut O, P),
+    show: impl FnOnce(&mut P, &mut egui::Ui, &mut EditRecorder<P>),
+) {
+    let mut part = get(owner);
+    let mut part_edits = EditRecorder::default();
+    show(&mut part, ui, &mut part_edits);
+    if part_edits.is_empty() {
+        return;
+    }
+    set(owner, part);
+    for TimedEdit { when, edit } in part_edits.take() {
+        edits.push(TimedEdit {
+            when,
+            edit: Arc::new(move |o: &mut O| {
+                let mut part = get(o);
+                edit(&mut part);
+                set(o, part);
+            }),
+        });
+    }
+}
+
+/// Shows a button that plays middle C on `instrument` for as long as it's
+/// held down, and returns where the notes it has played are now.
+fn show_test_note<I: HandlesMidi + 'static>(
+    ui: &mut egui::Ui,
+    instrument: &mut I,
+    edits: &mut EditRecorder<I>,
+) -> Vec<VoicePosition> {
+    const KEY: u8 = 60;
+    const VELOCITY: u8 = 127;
+
+    let id = ui.make_persistent_id("test-note");
+    let mut tracker: VoiceTracker = ui.data_mut(|d| d.get_temp(id)).unwrap_or_default();
+    let now = Instant::now();
+    let is_down = ui
+        .button("test note")
+        .on_hover_text("Plays middle C for as long as you hold the button")
+        .is_pointer_button_down_on();
+    if is_down && !tracker.is_held(KEY) {
+        edits.apply(instrument, |e| {
+            let _ = e.handle_midi_message(&new_note_on(KEY, VELOCITY));
+        });
+        tracker.note_on(KEY, now);
+    } else if !is_down && tracker.is_held(KEY) {
+        edits.apply(instrument, |e| {
+            let _ = e.handle_midi_message(&new_note_off(KEY, 0));
+        });
+        tracker.note_off(KEY, now);
+    }
+    tracker.prune(now);
+    let positions = tracker.positions(now);
+    ui.data_mut(|d| d.insert_temp(id, tracker));
+    positions
+}
+
+/// Draws an [Adsr] and lets the user drag its corners around. Scrolling over
+/// it zooms the time axis, and double-clicking fits the whole envelope again.
+/// Each of `voices` is a dot that follows the curve. These are only the test
+/// notes played from the editor; see [egui_prototype::envelope] for why.
+struct EnvelopeEditor<'a> {
+    adsr: &'a mut Adsr,
+    voices: &'a [VoicePosition],
+}
+
+/// What an [EnvelopeEditor] remembers between frames.
+#[derive(Clone, Debug, Default)]
+struct EnvelopeEditorState {
+    // How many seconds fit across the editor, or None to fit the envelope.
+    span: Option<f64>,
+    dragging: Option<Breakpoint>,
+}
+
+impl EnvelopeEditor<'_> {
+    const WIDTH: f32 = 240.0;
+    const HEIGHT: f32 = 100.0;
+    const MARGIN: f32 = 6.0;
+
+    /// How close, in points, the pointer has to be to grab a corner.
+    const GRAB_RADIUS: f32 = 8.0;
+
+    /// How much of the width the sustain takes up. The sustain lasts as long
+    /// as the note is held, so it has no length of its own to draw.
+    const HOLD_FRACTION: f64 = 0.2;
+
+    /// How much one point of scrolling zooms.
+    const ZOOM_RATE: f64 = 0.005;
+
+    /// Returns how many seconds it takes to show the whole envelope with
+    /// some room to spare.
+    fn fit(adsr: &Adsr) -> f64 {
+        let stages = adsr.attack + adsr.decay + adsr.release;
+        (stages / (0.9 - Self::HOLD_FRACTION)).max(0.1)
+    }
+}
+
+impl egui::Widget for EnvelopeEditor<'_> {
+    fn ui(self, ui: &mut Ui) -> egui::Response {
+        let (mut response, painter) = ui.allocate_painter(
+            egui::vec2(Self::WIDTH, Self::HEIGHT),
+            egui::Sense::click_and_drag(),
+        );
+        let mut state: EnvelopeEditorState =
+            ui.data_mut(|d| d.get_temp(response.id)).unwrap_or_default();
+
+        if response.hovered() {
+            let scroll = ui.input(|i| i.scroll_delta.y) as f64;
+            if scroll != 0.0 {
+                let span = state.span.unwrap_or_else(|| Self::fit(self.adsr));
+                state.span = Some(
+                    (span * (-scroll * Self::ZOOM_RATE).exp())
+                        .clamp(0.01, Adsr::MAX_STAGE_SECONDS * 4.0),
+                );
+            }
+        }
+        if response.double_clicked() {
+            state.span = None;
+        }
+        let span = state.span.unwrap_or_else(|| Self::fit(self.adsr));
+        let hold = span * Self::HOLD_FRACTION;
+
+        let rect = response.rect.shrink(Self::MARGIN);
+        let to_screen = |[time, level]: [f64; 2]| {
+            egui::pos2(
+                rect.left() + (time / span) as f32 * rect.width(),
+                rect.bottom() - level as f32 * rect.height(),
+            )
+        };
+        let from_screen = |pos: egui::Pos2| {
+            [
+                ((pos.x - rect.left()) / rect.width()) as f64 * span,
+                ((rect.bottom() - pos.y) / rect.height()) as f64,
+            ]
+        };
+        let breakpoints = [Breakpoint::Attack, Breakpoint::Decay, Breakpoint::Release];
+        let nearest = |adsr: &Adsr, pos: egui::Pos2| {
+            breakpoints
+                .iter()
+                .map(|breakpoint| {
+                    let distance = to_screen(adsr.breakpoint(*breakpoint, hold)).distance(pos);
+                    (*breakpoint, distance)
+                })
+                .filter(|(_, distance)| *distance <= Self::GRAB_RADIUS)
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(breakpoint, _)| breakpoint)
+        };
+
+        if response.drag_started() {
+            state.dragging = response
+                .interact_pointer_pos()
+                .and_then(|pos| nearest(self.adsr, pos));
+
+            // Hold the scale still while dragging, or fitting would move the
+            // corner out from under the pointer.
+            state.span = Some(span);
+        }
+        if let (Some(breakpoint), Some(pos)) = (state.dragging, response.interact_pointer_pos()) {
+            if response.dragged() {
+                let [time, level] = from_screen(pos);
+                self.adsr.drag(breakpoint, time, level, hold);
+                response.mark_changed();
+            }
+        }
+        if response.drag_released() {
+            state.dragging = None;
+        }
+        if state.dragging.is_some()
+            || response
+                .hover_pos()
+                .is_some_and(|pos| nearest(self.adsr, pos).is_some())
+        {
+            ui.ctx().set_cursor_icon(egui::CursorIcon::Grab);
+        }
+
+        let visuals = ui.visuals();
+        painter.rect_filled(response.rect, 2.0, visuals.extreme_bg_color);
+        let outline = self.adsr.outline(hold);
+        painter.add(egui::Shape::line(
+            outline.iter().map(|point| to_screen(*point)).collect(),
+            egui::Stroke::new(1.5, egui::Color32::LIGHT_GREEN),
+        ));
+        for breakpoint in breakpoints {
+            let color = if state.dragging == Some(breakpoint) {
+                egui::Color32::YELLOW
+            } else {
+                visuals.strong_text_color()
+            };
+            painter.circle_filled(
+                to_screen(self.adsr.breakpoint(breakpoint, hold)),
+                4.0,
+                color,
+            );
+        }
+        let sounding: Vec<&VoicePosition> = self
+            .voices
+            .iter()
+            .filter(|voice| self.adsr.is_sounding(voice))
+            .collect();
+        for voice in sounding.iter() {
+            let point = to_screen(self.adsr.playhead(voice, hold));
+            painter.line_segment(
+                [
+                    egui::pos2(point.x, rect.top()),
+                    egui::pos2(point.x, rect.bottom()),
+                ],
+                egui::Stroke::new(1.0, egui::Color32::YELLOW),
+            );
+            painter.circle_filled(point, 3.0, egui::Color32::YELLOW);
+        }
+        painter.text(
+            rect.right_top(),
+            egui::Align2::RIGHT_TOP,
+            format!("{span:0.2} s"),
+            egui::FontId::monospace(10.0),
+            visuals.weak_text_color(),
+        );
+        if !sounding.is_empty() {
+            ui.ctx().request_repaint();
+        }
+
+        ui.data_mut(|d| d.insert_temp(response.id, state));
+        response.on_hover_text(
+            "Drag the corners to shape the envelope. Scroll to zoom, and double-click to fit. \
+            Dots follow the test note; notes from the song aren't shown.",
+        )
+    }
+}
+
+/// Shows `envelope` in an [EnvelopeEditor], with sliders underneath for
+/// exact values. `voices` are test notes that have been played through it.
+fn show_envelope(
+    envelope: &mut EnvelopeNano,
+    ui: &mut egui::Ui,
+    edits: &mut EditRecorder<EnvelopeNano>,
+    voices: &[VoicePosition],
+) {
+    // Envelope stages are rarely more than a few seconds, but the short ones
+    // need the most precision.
+    fn seconds(value: &mut f64) -> Slider<'_> {
+        Slider::new(value, 0.0..=Adsr::MAX_STAGE_SECONDS)
+            .logarithmic(true)
+            .smallest_positive(0.001)
+            .suffix(" s")
+    }
+
+    let before = Adsr {
+        attack: envelope.attack(),
+        decay: envelope.decay(),
+        sustain: envelope.sustain().value(),
+        release: envelope.release(),
+    };
+    let mut adsr = before;
+    ui.add(EnvelopeEditor {
+        adsr: &mut adsr,
+        voices,
+    });
+    egui::Grid::new(ui.next_auto_id()).show(ui, |ui| {
+        ui.label("Attack");
+        ui.add(seconds(&mut adsr.attack));
         ui.end_row();
         ui.label("Decay");
-        if ui.add(DragValue::new(&mut decay).speed(0.1)).changed() {
-            self.set_decay(decay);
-        }
+        ui.add(seconds(&mut adsr.decay));
         ui.end_row();
         ui.label("Sustain");
-        if ui.add(DragValue::new(&mut sustain).speed(0.1)).changed() {
-            self.set_sustain(sustain.into());
-        }
+        ui.add(Slider::new(&mut adsr.sustain, Normal::range()));
         ui.end_row();
         ui.label("Release");
-        if ui.add(DragValue::new(&mut release).speed(0.1)).changed() {
-            self.set_release(release);
-        }
+        ui.add(seconds(&mut adsr.release));
         ui.end_row();
+    });
+
+    let Adsr {
+        attack,
+        decay,
+        sustain,
+        release,
+    } = adsr;
+    if attack != before.attack {
+        edits.apply(envelope, move |e| e.set_attack(attack));
+    }
+    if decay != before.decay {
+        edits.apply(envelope, move |e| e.set_decay(decay));
+    }
+    if sustain != before.sustain {
+        edits.apply(envelope, move |e| e.set_sustain(sustain.into()));
+    }
+    if release != before.release {
+        edits.apply(envelope, move |e| e.set_release(release));
     }
 }
 
-impl Shows for WelshSynth {
-    fn show(&mut self, ui: &mut egui::Ui) {
+/// Shows a slider for a filter's cutoff, and returns the new value if the
+/// user moved it.
+fn cutoff_slider(ui: &mut egui::Ui, mut cutoff: f64) -> Option<f64> {
+    ui.add(
+        Slider::new(&mut cutoff, FrequencyHz::range())
+            .logarithmic(true)
+            .text("Cutoff")
+            .suffix(" Hz"),
+    )
+    .changed()
+    .then_some(cutoff)
+}
+
+fn q_slider(ui: &mut egui::Ui, mut q: f64) -> Option<f64> {
+    ui.add(Slider::new(&mut q, 0.1..=20.0).logarithmic(true).text("Q"))
+        .changed()
+        .then_some(q)
+}
+
+fn bandwidth_slider(ui: &mut egui::Ui, mut octaves: f64) -> Option<f64> {
+    ui.add(
+        Slider::new(&mut octaves, 0.05..=4.0)
+            .logarithmic(true)
+            .text("Bandwidth")
+            .suffix(" oct"),
+    )
+    .changed()
+    .then_some(octaves)
+}
+
+fn gain_slider(ui: &mut egui::Ui, mut db_gain: f64) -> Option<f64> {
+    ui.add(
+        Slider::new(&mut db_gain, -24.0..=24.0)
+            .text("Gain")
+            .suffix(" dB"),
+    )
+    .changed()
+    .then_some(db_gain)
+}
+
+fn passband_ripple_slider(ui: &mut egui::Ui, mut ripple: f64) -> Option<f64> {
+    ui.add(Slider::new(&mut ripple, 0.0..=10.0).text("Passband"))
+        .changed()
+        .then_some(ripple)
+}
+
+/// Implements [ShowsEdits] for a biquad filter: a slider for its cutoff, and
+/// one for each of its other settings, given as `slider: getter => setter`.
+/// The filters don't expose their coefficients, so there's no response plot.
+macro_rules! impl_filter_editor {
+    ($filter:ty, [$($slider:ident: $get:ident => $set:ident),*]) => {
+        impl ShowsEdits for $filter {
+            fn show(&mut self, ui: &mut egui::Ui, edits: &mut EditRecorder<Self>) {
+                if let Some(cutoff) = cutoff_slider(ui, self.cutoff().value()) {
+                    edits.apply(self, move |e| e.set_cutoff(cutoff.into()));
+                }
+                $(
+                    if let Some(value) = $slider(ui, self.$get()) {
+                        edits.apply(self, move |e| e.$set(value));
+                    }
+                )*
+            }
+        }
+    };
+}
+
+impl_filter_editor!(BiQuadFilterLowPass12db, [q_slider: q => set_q]);
+impl_filter_editor!(
+    BiQuadFilterLowPass24db,
+    [passband_ripple_slider: passband_ripple => set_passband_ripple]
+);
+impl_filter_editor!(BiQuadFilterHighPass, [q_slider: q => set_q]);
+impl_filter_editor!(BiQuadFilterAllPass, [q_slider: q => set_q]);
+impl_filter_editor!(
+    BiQuadFilterBandPass,
+    [bandwidth_slider: bandwidth => set_bandwidth]
+);
+impl_filter_editor!(
+    BiQuadFilterBandStop,
+    [bandwidth_slider: bandwidth => set_bandwidth]
+);
+impl_filter_editor!(
+    BiQuadFilterPeakingEq,
+    [q_slider: q => set_q, gain_slider: db_gain => set_db_gain]
+);
+impl_filter_editor!(
+    BiQuadFilterLowShelf,
+    [gain_slider: db_gain => set_db_gain]
+);
+impl_filter_editor!(
+    BiQuadFilterHighShelf,
+    [gain_slider: db_gain => set_db_gain]
+);
+
+impl ShowsEdits for BiQuadFilterNone {
+    fn show(&mut self, ui: &mut egui::Ui, _edits: &mut EditRecorder<Self>) {
+        ui.label("Passes audio through unchanged");
+    }
+}
+
+impl ShowsEdits for WelshSynth {
+    fn show(&mut self, ui: &mut egui::Ui, edits: &mut EditRecorder<Self>) {
         let mut pan = self.pan().value();
         if ui
             .add(
@@ -272,40 +2363,31 @@ impl Shows for WelshSynth {
             )
             .changed()
         {
-            self.set_pan(pan.into());
-        };
-        Envelope::new_with(self.envelope().clone()).show(ui);
-    }
-}
-
-impl Shows for BiQuadFilterLowPass24db {
-    fn show(&mut self, ui: &mut egui::Ui) {
-        let mut cutoff = self.cutoff().value();
-        let mut pbr = self.passband_ripple();
-        if ui
-            .add(Slider::new(&mut cutoff, FrequencyHz::range()).text("Cutoff"))
-            .changed()
-        {
-            self.set_cutoff(cutoff.into());
-        };
-        if ui
-            .add(Slider::new(&mut pbr, 0.0..=10.0).text("Passband"))
-            .changed()
-        {
-            self.set_passband_ripple(pbr)
+            edits.apply(self, move |e| e.set_pan(pan.into()));
         };
+        let voices = show_test_note(ui, self, edits);
+        ui.collapsing("Amplitude envelope", |ui| {
+            show_part_with(
+                ui,
+                self,
+                edits,
+                |s| s.envelope().clone(),
+                |s, p| s.set_envelope(p),
+                |e, ui, edits| show_envelope(e, ui, edits, &voices),
+            )
+        });
     }
 }
 
-impl Shows for LfoController {
-    fn show(&mut self, ui: &mut egui::Ui) {
+impl ShowsEdits for LfoController {
+    fn show(&mut self, ui: &mut egui::Ui, edits: &mut EditRecorder<Self>) {
         let mut frequency = self.frequency().value();
         let mut waveform = self.waveform();
         if ui
             .add(Slider::new(&mut frequency, LfoController::frequency_range()).text("Frequency"))
             .changed()
         {
-            self.set_frequency(frequency.into());
+            edits.apply(self, move |e| e.set_frequency(frequency.into()));
         };
         ComboBox::new(ui.next_auto_id(), "Waveform")
             .selected_text(waveform.to_string())
@@ -316,7 +2398,7 @@ impl Shows for LfoController {
             });
         if waveform != self.waveform() {
             eprintln!("changed {} {}", self.waveform(), waveform);
-            self.set_waveform(waveform);
+            edits.apply(self, move |e| e.set_waveform(waveform));
         }
     }
 }
@@ -339,8 +2421,28 @@ impl Shows for Mixer {
     }
 }
 
-impl Shows for Orchestrator {
-    fn show(&mut self, ui: &mut egui::Ui) {
+/// Shows an entity's editor, then wraps each of its edits so that it finds
+/// the same entity in the audio thread's copy of the orchestrator.
+macro_rules! show_entity {
+    ($variant:ident, $entity:expr, $uid:expr, $ui:expr, $edits:expr) => {{
+        let mut entity_edits = EditRecorder::default();
+        $entity.show($ui, &mut entity_edits);
+        let uid = $uid;
+        for TimedEdit { when, edit } in entity_edits.take() {
+            $edits.push(TimedEdit {
+                when,
+                edit: Arc::new(move |o: &mut Orchestrator| {
+                    if let Some(groove_orchestration::Entity::$variant(e)) = o.get_mut(uid) {
+                        edit(e);
+                    }
+                }),
+            });
+        }
+    }};
+}
+
+impl ShowsEdits for Orchestrator {
+    fn show(&mut self, ui: &mut egui::Ui, edits: &mut EditRecorder<Self>) {
         ui.with_layout(egui::Layout::left_to_right(egui::Align::TOP), |ui| {
             let uids: Vec<usize> = self.entity_iter().map(|(uid, _entity)| *uid).collect();
             for uid in uids {
@@ -356,34 +2458,34 @@ impl Shows for Orchestrator {
                                         ui.label(entity.as_has_uid().name());
                                     }
                                     groove_orchestration::Entity::BiQuadFilterAllPass(e) => {
-                                        ui.label(entity.as_has_uid().name());
+                                        show_entity!(BiQuadFilterAllPass, e, uid, ui, edits);
                                     }
                                     groove_orchestration::Entity::BiQuadFilterBandPass(e) => {
-                                        ui.label(entity.as_has_uid().name());
+                                        show_entity!(BiQuadFilterBandPass, e, uid, ui, edits);
                                     }
                                     groove_orchestration::Entity::BiQuadFilterBandStop(e) => {
-                                        ui.label(entity.as_has_uid().name());
+                                        show_entity!(BiQuadFilterBandStop, e, uid, ui, edits);
                                     }
                                     groove_orchestration::Entity::BiQuadFilterHighPass(e) => {
-                                        ui.label(entity.as_has_uid().name());
+                                        show_entity!(BiQuadFilterHighPass, e, uid, ui, edits);
                                     }
                                     groove_orchestration::Entity::BiQuadFilterHighShelf(e) => {
-                                        ui.label(entity.as_has_uid().name());
+                                        show_entity!(BiQuadFilterHighShelf, e, uid, ui, edits);
                                     }
                                     groove_orchestration::Entity::BiQuadFilterLowPass12db(e) => {
-                                        ui.label(entity.as_has_uid().name());
+                                        show_entity!(BiQuadFilterLowPass12db, e, uid, ui, edits);
                                     }
                                     groove_orchestration::Entity::BiQuadFilterLowPass24db(e) => {
-                                        e.show(ui);
+                                        show_entity!(BiQuadFilterLowPass24db, e, uid, ui, edits);
                                     }
                                     groove_orchestration::Entity::BiQuadFilterLowShelf(e) => {
-                                        ui.label(entity.as_has_uid().name());
+                                        show_entity!(BiQuadFilterLowShelf, e, uid, ui, edits);
                                     }
                                     groove_orchestration::Entity::BiQuadFilterNone(e) => {
-                                        ui.label(entity.as_has_uid().name());
+                                        show_entity!(BiQuadFilterNone, e, uid, ui, edits);
                                     }
                                     groove_orchestration::Entity::BiQuadFilterPeakingEq(e) => {
-                                        ui.label(entity.as_has_uid().name());
+                                        show_entity!(BiQuadFilterPeakingEq, e, uid, ui, edits);
                                     }
                                     groove_orchestration::Entity::Bitcrusher(e) => {
                                         ui.label(entity.as_has_uid().name());
@@ -416,7 +2518,7 @@ impl Shows for Orchestrator {
                                         ui.label(entity.as_has_uid().name());
                                     }
                                     groove_orchestration::Entity::LfoController(e) => {
-                                        e.show(ui);
+                                        show_entity!(LfoController, e, uid, ui, edits);
                                     }
                                     groove_orchestration::Entity::Limiter(e) => {
                                         ui.label(entity.as_has_uid().name());
@@ -469,7 +2571,7 @@ impl Shows for Orchestrator {
                                         ui.label(entity.as_has_uid().name());
                                     }
                                     groove_orchestration::Entity::WelshSynth(e) => {
-                                        e.show(ui);
+                                        show_entity!(WelshSynth, e, uid, ui, edits);
                                     }
                                 })
                             });
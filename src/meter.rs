@@ -0,0 +1,524 @@
+//! Measures how loud the output is: peak with hold, RMS, loudness in LUFS as
+//! ITU-R BS.1770 defines it, and whether anything clipped.
+//!
+//! [Meter] does the measuring wherever the samples are. The audio thread
+//! publishes its readings to a [MeterSnapshot] so that the UI can show them
+//! without waiting on a lock, and offline renders read them directly.
+
+use groove_core::StereoSample;
+use std::{
+    collections::VecDeque,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+/// What a [Meter] has measured so far. Levels are linear amplitudes, where
+/// 1.0 is full scale, and loudness is in LUFS.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeterReading {
+    /// The recent peak of each channel, falling back gradually after each
+    /// new peak.
+    pub peak: [f64; 2],
+
+    /// The highest peak of each channel over the last couple of seconds.
+    pub peak_hold: [f64; 2],
+
+    /// The highest peak of each channel since the meter was last reset.
+    pub max_peak: [f64; 2],
+
+    /// Each channel's RMS level over roughly the last 300ms.
+    pub rms: [f64; 2],
+
+    /// Loudness over the last 400ms.
+    pub momentary_lufs: f64,
+
+    /// Loudness over the last 3s.
+    pub short_term_lufs: f64,
+
+    /// Gated loudness since the meter was last reset.
+    pub integrated_lufs: f64,
+
+    /// Whether each channel has reached full scale since the clip indicators
+    /// were last cleared.
+    pub clipped: [bool; 2],
+}
+impl Default for MeterReading {
+    fn default() -> Self {
+        Self {
+            peak: Default::default(),
+            peak_hold: Default::default(),
+            max_peak: Default::default(),
+            rms: Default::default(),
+            momentary_lufs: f64::NEG_INFINITY,
+            short_term_lufs: f64::NEG_INFINITY,
+            integrated_lufs: f64::NEG_INFINITY,
+            clipped: Default::default(),
+        }
+    }
+}
+
+/// Converts a linear amplitude to decibels relative to full scale.
+pub fn to_dbfs(amplitude: f64) -> f64 {
+    20.0 * amplitude.log10()
+}
+
+/// Measures a stream of stereo frames.
+#[derive(Debug)]
+pub struct Meter {
+    sample_rate: usize,
+    reading: MeterReading,
+
+    // How many more frames each channel's peak hold lasts.
+    hold_frames_left: [usize; 2],
+
+    // How much a peak falls each frame once it's no longer being pushed up.
+    peak_falloff: f64,
+
+    // The running mean square of each channel, and how quickly it follows
+    // the signal.
+    mean_square: [f64; 2],
+    rms_coefficient: f64,
+
+    // The K-weighting filter for each channel.
+    k_weighting: [KWeighting; 2],
+
+    // The mean square of the K-weighted signal, summed over channels, for
+    // each of the most recent 100ms steps. Loudness is measured over blocks
+    // of these.
+    steps: VecDeque<f64>,
+    step_sum: f64,
+    step_frames: usize,
+    frames_per_step: usize,
+
+    // Every gating block seen so far, binned by loudness so that the
+    // integrated loudness can be worked out at any time without keeping them
+    // all.
+    histogram: LoudnessHistogram,
+}
+impl Meter {
+    /// How long a peak is held before it's allowed to fall.
+    const PEAK_HOLD_SECONDS: f64 = 2.0;
+
+    /// How fast the peak falls back after each new one.
+    const PEAK_FALLOFF_DB_PER_SECOND: f64 = 20.0;
+
+    /// The time constant of the RMS measurement.
+    const RMS_SECONDS: f64 = 0.3;
+
+    /// BS.1770 measures loudness over 400ms blocks that overlap by 75%, so
+    /// a new block starts every 100ms.
+    const STEP_SECONDS: f64 = 0.1;
+    const STEPS_PER_MOMENTARY: usize = 4;
+    const STEPS_PER_SHORT_TERM: usize = 30;
+
+    pub fn new(sample_rate: usize) -> Self {
+        let frames_per_second = sample_rate as f64;
+        Self {
+            sample_rate,
+            reading: Default::default(),
+            hold_frames_left: Default::default(),
+            peak_falloff: 10.0f64
+                .powf(-Self::PEAK_FALLOFF_DB_PER_SECOND / 20.0 / frames_per_second),
+            mean_square: Default::default(),
+            rms_coefficient: 1.0 - (-1.0 / (Self::RMS_SECONDS * frames_per_second)).exp(),
+            k_weighting: [KWeighting::new(sample_rate), KWeighting::new(sample_rate)],
+            steps: VecDeque::with_capacity(Self::STEPS_PER_SHORT_TERM),
+            step_sum: Default::default(),
+            step_frames: Default::default(),
+            frames_per_step: ((Self::STEP_SECONDS * frames_per_second).round() as usize).max(1),
+            histogram: Default::default(),
+        }
+    }
+
+    pub fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    /// Forgets everything measured so far.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.sample_rate);
+    }
+
+    /// Turns off the clip indicators without disturbing anything else.
+    pub fn clear_clips(&mut self) {
+        self.reading.clipped = Default::default();
+    }
+
+    pub fn reading(&self) -> MeterReading {
+        self.reading
+    }
+
+    pub fn process(&mut self, frames: &[StereoSample]) {
+        let hold_frames = (Self::PEAK_HOLD_SECONDS * self.sample_rate as f64) as usize;
+        for frame in frames {
+            let mut weighted_square = 0.0;
+            for (channel, value) in [frame.0 .0, frame.1 .0].into_iter().enumerate() {
+                let level = value.abs();
+                let reading = &mut self.reading;
+                reading.peak[channel] = level.max(reading.peak[channel] * self.peak_falloff);
+                if level >= reading.peak_hold[channel] || self.hold_frames_left[channel] == 0 {
+                    reading.peak_hold[channel] = level.max(reading.peak[channel]);
+                    self.hold_frames_left[channel] = hold_frames;
+                } else {
+                    self.hold_frames_left[channel] -= 1;
+                }
+                reading.max_peak[channel] = reading.max_peak[channel].max(level);
+                if level >= 1.0 {
+                    reading.clipped[channel] = true;
+                }
+
+                self.mean_square[channel] +=
+                    self.rms_coefficient * (value * value - self.mean_square[channel]);
+
+                let weighted = self.k_weighting[channel].process(value);
+                weighted_square += weighted * weighted;
+            }
+
+            self.step_sum += weighted_square;
+            self.step_frames += 1;
+            if self.step_frames == self.frames_per_step {
+                self.finish_step();
+            }
+        }
+        for channel in 0..2 {
+            self.reading.rms[channel] = self.mean_square[channel].sqrt();
+        }
+    }
+
+    fn finish_step(&mut self) {
+        if self.steps.len() == Self::STEPS_PER_SHORT_TERM {
+            self.steps.pop_front();
+        }
+        self.steps
+            .push_back(self.step_sum / self.step_frames as f64);
+        self.step_sum = 0.0;
+        self.step_frames = 0;
+
+        if self.steps.len() >= Self::STEPS_PER_MOMENTARY {
+            let momentary = self.mean_of_last(Self::STEPS_PER_MOMENTARY);
+            self.reading.momentary_lufs = Self::power_to_lufs(momentary);
+            self.histogram.add(momentary);
+            self.reading.integrated_lufs = self.histogram.integrated_lufs();
+        }
+        if self.steps.len() == Self::STEPS_PER_SHORT_TERM {
+            self.reading.short_term_lufs =
+                Self::power_to_lufs(self.mean_of_last(Self::STEPS_PER_SHORT_TERM));
+        }
+    }
+
+    fn mean_of_last(&self, count: usize) -> f64 {
+        self.steps.iter().rev().take(count).sum::<f64>() / count as f64
+    }
+
+    fn power_to_lufs(power: f64) -> f64 {
+        -0.691 + 10.0 * power.log10()
+    }
+}
+
+/// The two-stage filter that BS.1770 applies before measuring loudness: a
+/// high shelf that models the head, then a high-pass that ignores rumble.
+#[derive(Debug)]
+struct KWeighting {
+    stages: [Biquad; 2],
+}
+impl KWeighting {
+    fn new(sample_rate: usize) -> Self {
+        // These are the analog prototypes behind the 48KHz coefficients in
+        // the standard, which lets us match them at any rate.
+        let sample_rate = sample_rate as f64;
+        let shelf = {
+            let k = (std::f64::consts::PI * 1681.974450955533 / sample_rate).tan();
+            let q = 0.7071752369554196;
+            let vh = 10.0f64.powf(3.999843853973347 / 20.0);
+            let vb = vh.powf(0.4996667741545416);
+            let a0 = 1.0 + k / q + k * k;
+            Biquad::new(
+                [
+                    (vh + vb * k / q + k * k) / a0,
+                    2.0 * (k * k - vh) / a0,
+                    (vh - vb * k / q + k * k) / a0,
+                ],
+                [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            )
+        };
+        let high_pass = {
+            let k = (std::f64::consts::PI * 38.13547087602444 / sample_rate).tan();
+            let q = 0.5003270373238773;
+            let a0 = 1.0 + k / q + k * k;
+            Biquad::new(
+                [1.0, -2.0, 1.0],
+                [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            )
+        };
+        Self {
+            stages: [shelf, high_pass],
+        }
+    }
+
+    fn process(&mut self, value: f64) -> f64 {
+        self.stages
+            .iter_mut()
+            .fold(value, |value, stage| stage.process(value))
+    }
+}
+
+#[derive(Debug)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    state: [f64; 2],
+}
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self {
+            b,
+            a,
+            state: Default::default(),
+        }
+    }
+
+    // Transposed direct form II.
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.state[0];
+        self.state[0] = self.b[1] * input - self.a[0] * output + self.state[1];
+        self.state[1] = self.b[2] * input - self.a[1] * output;
+        output
+    }
+}
+
+/// Gating blocks, binned by loudness in tenths of a LU.
+#[derive(Debug)]
+struct LoudnessHistogram {
+    counts: Vec<usize>,
+    powers: Vec<f64>,
+}
+impl Default for LoudnessHistogram {
+    fn default() -> Self {
+        let bins = ((Self::CEILING_LUFS - Self::ABSOLUTE_GATE_LUFS) / Self::BIN_LU) as usize + 1;
+        Self {
+            counts: vec![0; bins],
+            powers: vec![0.0; bins],
+        }
+    }
+}
+impl LoudnessHistogram {
+    /// Blocks quieter than this don't count toward integrated loudness.
+    const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+
+    /// Blocks more than this much quieter than the average of the rest don't
+    /// count either.
+    const RELATIVE_GATE_LU: f64 = -10.0;
+
+    /// Anything louder goes in the top bin.
+    const CEILING_LUFS: f64 = 10.0;
+    const BIN_LU: f64 = 0.1;
+
+    fn add(&mut self, power: f64) {
+        let lufs = Meter::power_to_lufs(power);
+        if lufs.is_nan() || lufs < Self::ABSOLUTE_GATE_LUFS {
+            return;
+        }
+        let bin = (((lufs - Self::ABSOLUTE_GATE_LUFS) / Self::BIN_LU) as usize)
+            .min(self.counts.len() - 1);
+        self.counts[bin] += 1;
+        self.powers[bin] += power;
+    }
+
+    fn integrated_lufs(&self) -> f64 {
+        let (count, power) = self.sum_from(0);
+        if count == 0 {
+            return f64::NEG_INFINITY;
+        }
+        let relative_gate = Meter::power_to_lufs(power / count as f64) + Self::RELATIVE_GATE_LU;
+        let first_bin = ((relative_gate - Self::ABSOLUTE_GATE_LUFS) / Self::BIN_LU)
+            .ceil()
+            .max(0.0) as usize;
+        match self.sum_from(first_bin) {
+            (0, _) => f64::NEG_INFINITY,
+            (count, power) => Meter::power_to_lufs(power / count as f64),
+        }
+    }
+
+    fn sum_from(&self, first_bin: usize) -> (usize, f64) {
+        let first_bin = first_bin.min(self.counts.len());
+        (
+            self.counts[first_bin..].iter().sum(),
+            self.powers[first_bin..].iter().sum(),
+        )
+    }
+}
+
+/// The latest [MeterReading], shared between the thread that meters and the
+/// threads that look.
+#[derive(Debug, Default)]
+pub struct MeterSnapshot {
+    // f64s, stored as their bits.
+    peak: [AtomicU64; 2],
+    peak_hold: [AtomicU64; 2],
+    max_peak: [AtomicU64; 2],
+    rms: [AtomicU64; 2],
+    momentary_lufs: AtomicU64,
+    short_term_lufs: AtomicU64,
+    integrated_lufs: AtomicU64,
+
+    clipped: [AtomicBool; 2],
+
+    // Set by the UI when the user clears the clip indicators, and cleared by
+    // the metering thread once it has done so.
+    clear_requested: AtomicBool,
+}
+impl MeterSnapshot {
+    pub fn publish(&self, reading: &MeterReading) {
+        for channel in 0..2 {
+            Self::store(&self.peak[channel], reading.peak[channel]);
+            Self::store(&self.peak_hold[channel], reading.peak_hold[channel]);
+            Self::store(&self.max_peak[channel], reading.max_peak[channel]);
+            Self::store(&self.rms[channel], reading.rms[channel]);
+            self.clipped[channel].store(reading.clipped[channel], Ordering::Relaxed);
+        }
+        Self::store(&self.momentary_lufs, reading.momentary_lufs);
+        Self::store(&self.short_term_lufs, reading.short_term_lufs);
+        Self::store(&self.integrated_lufs, reading.integrated_lufs);
+    }
+
+    pub fn reading(&self) -> MeterReading {
+        MeterReading {
+            peak: [0, 1].map(|channel| Self::load(&self.peak[channel])),
+            peak_hold: [0, 1].map(|channel| Self::load(&self.peak_hold[channel])),
+            max_peak: [0, 1].map(|channel| Self::load(&self.max_peak[channel])),
+            rms: [0, 1].map(|channel| Self::load(&self.rms[channel])),
+            momentary_lufs: Self::load(&self.momentary_lufs),
+            short_term_lufs: Self::load(&self.short_term_lufs),
+            integrated_lufs: Self::load(&self.integrated_lufs),
+            clipped: [0, 1].map(|channel| self.clipped[channel].load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Asks the metering thread to clear the clip indicators.
+    pub fn request_clear_clips(&self) {
+        self.clear_requested.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns true, once, for each call to
+    /// [request_clear_clips()](MeterSnapshot::request_clear_clips).
+    pub fn take_clear_clips_request(&self) -> bool {
+        self.clear_requested.swap(false, Ordering::Relaxed)
+    }
+
+    fn store(atomic: &AtomicU64, value: f64) {
+        atomic.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    fn load(atomic: &AtomicU64) -> f64 {
+        f64::from_bits(atomic.load(Ordering::Relaxed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use groove_core::Sample;
+
+    fn sine(frequency: f64, amplitude: f64, sample_rate: usize, seconds: f64) -> Vec<StereoSample> {
+        (0..(seconds * sample_rate as f64) as usize)
+            .map(|i| {
+                let value = amplitude
+                    * (2.0 * std::f64::consts::PI * frequency * i as f64 / sample_rate as f64)
+                        .sin();
+                StereoSample(Sample(value), Sample(value))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn k_weighting_matches_the_standard_at_48k() {
+        let filter = KWeighting::new(48000);
+        let expected_shelf = (
+            [1.53512485958697, -2.69169618940638, 1.19839281085285],
+            [-1.69065929318241, 0.73248077421585],
+        );
+        let expected_high_pass = ([1.0, -2.0, 1.0], [-1.99004745483398, 0.99007225036621]);
+        for (stage, (b, a)) in filter
+            .stages
+            .iter()
+            .zip([expected_shelf, expected_high_pass])
+        {
+            for (actual, expected) in stage.b.iter().zip(b).chain(stage.a.iter().zip(a)) {
+                assert!((actual - expected).abs() < 1e-8, "{actual} != {expected}");
+            }
+        }
+    }
+
+    #[test]
+    fn measures_a_full_scale_sine() {
+        // BS.1770 says that a 0dBFS, 997Hz sine in one channel reads -3.01
+        // LUFS, so one in both channels reads 0.
+        for sample_rate in [44100, 48000] {
+            let mut meter = Meter::new(sample_rate);
+            meter.process(&sine(997.0, 1.0, sample_rate, 5.0));
+            let reading = meter.reading();
+            for lufs in [
+                reading.momentary_lufs,
+                reading.short_term_lufs,
+                reading.integrated_lufs,
+            ] {
+                assert!(lufs.abs() < 0.05, "{sample_rate}: {lufs} LUFS");
+            }
+            for channel in 0..2 {
+                assert!((reading.peak_hold[channel] - 1.0).abs() < 1e-3);
+                assert!((reading.rms[channel] - 0.5f64.sqrt()).abs() < 0.01);
+                assert!(reading.clipped[channel]);
+            }
+        }
+    }
+
+    #[test]
+    fn integrated_loudness_ignores_quiet_passages() {
+        let mut meter = Meter::new(48000);
+        let mut loud = sine(997.0, 0.1, 48000, 10.0);
+
+        // Silence and near-silence fall below the gates.
+        loud.extend(sine(997.0, 0.0, 48000, 10.0));
+        loud.extend(sine(997.0, 0.001, 48000, 10.0));
+        meter.process(&loud);
+        let reading = meter.reading();
+        assert!(
+            (reading.integrated_lufs - -20.0).abs() < 0.1,
+            "{} LUFS",
+            reading.integrated_lufs
+        );
+        assert!(reading.short_term_lufs < -55.0);
+        assert_eq!(reading.clipped, [false, false]);
+    }
+
+    #[test]
+    fn peaks_hold_then_fall() {
+        let mut meter = Meter::new(1000);
+        meter.process(&[StereoSample(Sample(0.5), Sample(-0.25))]);
+        meter.process(&vec![StereoSample(Sample(0.0), Sample(0.0)); 100]);
+        let reading = meter.reading();
+        assert_eq!(reading.peak_hold, [0.5, 0.25]);
+        assert_eq!(reading.max_peak, [0.5, 0.25]);
+        assert!(reading.peak[0] < 0.5 && reading.peak[0] > 0.35);
+
+        // Two seconds later, the hold lets go.
+        meter.process(&vec![StereoSample(Sample(0.0), Sample(0.0)); 2000]);
+        assert!(meter.reading().peak_hold[0] < 0.5);
+        assert_eq!(meter.reading().max_peak, [0.5, 0.25]);
+    }
+
+    #[test]
+    fn snapshot_round_trips_and_clears_clips() {
+        let mut meter = Meter::new(48000);
+        meter.process(&sine(997.0, 1.5, 48000, 1.0));
+        let snapshot = MeterSnapshot::default();
+        snapshot.publish(&meter.reading());
+        assert_eq!(snapshot.reading(), meter.reading());
+
+        assert!(!snapshot.take_clear_clips_request());
+        snapshot.request_clear_clips();
+        assert!(snapshot.take_clear_clips_request());
+        assert!(!snapshot.take_clear_clips_request());
+        meter.clear_clips();
+        assert_eq!(meter.reading().clipped, [false, false]);
+    }
+}
@@ -0,0 +1,501 @@
+//! Moves parameter changes from the UI thread to the audio thread, and state
+//! back the other way, without either side ever waiting on a lock.
+//!
+//! The UI keeps its own copy of whatever it edits. Each change is applied to
+//! that copy right away and recorded as an [Edit], which is then replayed on
+//! the audio thread's copy the next time it generates audio. Each edit carries
+//! the moment the UI made it, so that the audio thread can place it at the
+//! matching point in the output rather than at the start of whichever buffer
+//! it happens to be working on.
+
+use crossbeam::queue::ArrayQueue;
+use std::{
+    collections::VecDeque,
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+/// A change to a `T`. Edits can be applied any number of times, so that the
+/// same change can be made to more than one copy.
+pub type Edit<T> = Arc<dyn Fn(&mut T) + Send + Sync>;
+
+/// An [Edit] along with when the UI made it.
+pub struct TimedEdit<T> {
+    /// When the edit happened, or None if it should take effect as soon as
+    /// possible, wherever that falls in the output.
+    pub when: Option<Instant>,
+    pub edit: Edit<T>,
+}
+impl<T> Clone for TimedEdit<T> {
+    fn clone(&self) -> Self {
+        Self {
+            when: self.when,
+            edit: Arc::clone(&self.edit),
+        }
+    }
+}
+impl<T> Debug for TimedEdit<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TimedEdit")
+            .field("when", &self.when)
+            .finish()
+    }
+}
+impl<T> TimedEdit<T> {
+    /// An edit that happened just now.
+    pub fn now(edit: Edit<T>) -> Self {
+        Self {
+            when: Some(Instant::now()),
+            edit,
+        }
+    }
+
+    /// An edit that isn't tied to any moment, such as a transport change.
+    pub fn immediate(edit: Edit<T>) -> Self {
+        Self { when: None, edit }
+    }
+}
+
+/// Collects the edits that the UI makes during a frame.
+pub struct EditRecorder<T> {
+    edits: Vec<TimedEdit<T>>,
+}
+impl<T> Default for EditRecorder<T> {
+    fn default() -> Self {
+        Self {
+            edits: Vec::default(),
+        }
+    }
+}
+impl<T> Debug for EditRecorder<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EditRecorder")
+            .field("len", &self.edits.len())
+            .finish()
+    }
+}
+impl<T: 'static> EditRecorder<T> {
+    /// Applies `edit` to `target` and records it, stamped with the current
+    /// time, so that it can be replayed elsewhere.
+    pub fn apply<F>(&mut self, target: &mut T, edit: F)
+    where
+        F: Fn(&mut T) + Send + Sync + 'static,
+    {
+        edit(target);
+        self.edits.push(TimedEdit::now(Arc::new(edit)));
+    }
+
+    /// Like [EditRecorder::apply()], but the replayed edit takes effect as
+    /// soon as possible instead of at a particular moment.
+    pub fn apply_immediately<F>(&mut self, target: &mut T, edit: F)
+    where
+        F: Fn(&mut T) + Send + Sync + 'static,
+    {
+        edit(target);
+        self.edits.push(TimedEdit::immediate(Arc::new(edit)));
+    }
+
+    /// Records an edit that has already been applied locally.
+    pub fn push(&mut self, edit: TimedEdit<T>) {
+        self.edits.push(edit);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+
+    /// Returns the recorded edits, oldest first, and forgets them.
+    pub fn take(&mut self) -> Vec<TimedEdit<T>> {
+        std::mem::take(&mut self.edits)
+    }
+}
+
+/// Every edit made to something since it was loaded. Replaying them on a
+/// freshly loaded copy brings it up to date with the one they were made to.
+pub struct EditLog<T> {
+    edits: Vec<Edit<T>>,
+}
+impl<T> Default for EditLog<T> {
+    fn default() -> Self {
+        Self {
+            edits: Vec::default(),
+        }
+    }
+}
+impl<T> Clone for EditLog<T> {
+    fn clone(&self) -> Self {
+        Self {
+            edits: self.edits.clone(),
+        }
+    }
+}
+impl<T> Debug for EditLog<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EditLog")
+            .field("len", &self.edits.len())
+            .finish()
+    }
+}
+impl<T> EditLog<T> {
+    pub fn record(&mut self, edit: &TimedEdit<T>) {
+        self.edits.push(Arc::clone(&edit.edit));
+    }
+
+    /// Applies every recorded edit to `target`, oldest first.
+    pub fn replay(&self, target: &mut T) {
+        for edit in &self.edits {
+            edit(target);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.edits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+}
+
+/// Creates the two ends of a lock-free queue of edits. `capacity` limits how
+/// many edits can be waiting for the audio thread at once; any more wait in
+/// the sender's backlog until there's room.
+pub fn edit_channel<T>(capacity: usize) -> (EditSender<T>, EditReceiver<T>) {
+    let queue = Arc::new(ArrayQueue::new(capacity));
+    (
+        EditSender {
+            queue: Arc::clone(&queue),
+            backlog: VecDeque::default(),
+        },
+        EditReceiver { queue },
+    )
+}
+
+/// The UI's end of an [edit_channel()].
+pub struct EditSender<T> {
+    queue: Arc<ArrayQueue<TimedEdit<T>>>,
+
+    // Edits that didn't fit in the queue. They're sent in order before any
+    // newer ones.
+    backlog: VecDeque<TimedEdit<T>>,
+}
+impl<T> Debug for EditSender<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EditSender")
+            .field("queued", &self.queue.len())
+            .field("backlog", &self.backlog.len())
+            .finish()
+    }
+}
+impl<T> EditSender<T> {
+    /// Sends `edit` to the audio thread, or holds onto it if the queue is
+    /// full. Never blocks.
+    pub fn send(&mut self, edit: TimedEdit<T>) {
+        self.flush();
+        if !self.backlog.is_empty() {
+            self.backlog.push_back(edit);
+        } else if let Err(edit) = self.queue.push(edit) {
+            self.backlog.push_back(edit);
+        }
+    }
+
+    /// Moves as much of the backlog into the queue as will fit. Returns true
+    /// if nothing is left in the backlog.
+    pub fn flush(&mut self) -> bool {
+        while let Some(edit) = self.backlog.pop_front() {
+            if let Err(edit) = self.queue.push(edit) {
+                self.backlog.push_front(edit);
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Returns the number of edits that haven't made it into the queue yet.
+    pub fn backlog_len(&self) -> usize {
+        self.backlog.len()
+    }
+}
+
+/// The audio thread's end of an [edit_channel()].
+pub struct EditReceiver<T> {
+    queue: Arc<ArrayQueue<TimedEdit<T>>>,
+}
+impl<T> Debug for EditReceiver<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EditReceiver")
+            .field("queued", &self.queue.len())
+            .finish()
+    }
+}
+impl<T> EditReceiver<T> {
+    /// Applies every waiting edit to `target`, in the order they were sent,
+    /// and returns how many there were. Ignores when they happened.
+    pub fn apply_all(&self, target: &mut T) -> usize {
+        let mut count = 0;
+        while let Some(edit) = self.queue.pop() {
+            (edit.edit)(target);
+            count += 1;
+        }
+        count
+    }
+
+    /// Returns the oldest waiting edit, if any.
+    pub fn pop(&self) -> Option<TimedEdit<T>> {
+        self.queue.pop()
+    }
+}
+
+/// The parts of the audio thread's state that the UI displays every frame.
+/// The audio thread updates it after generating each batch of audio.
+#[derive(Debug, Default)]
+pub struct TransportSnapshot {
+    // f64s, stored as their bits.
+    bpm: AtomicU64,
+    seconds: AtomicU64,
+    output_latency: AtomicU64,
+
+    is_playing: AtomicBool,
+
+    // The rate the audio thread's project runs at. It changes along with the
+    // project, so the edit that swaps in a new project sets it.
+    project_sample_rate: AtomicUsize,
+
+    // The one thing here that the UI writes and the audio thread reads:
+    // whether to start over when the song ends, rather than stopping.
+    is_looping: AtomicBool,
+}
+impl TransportSnapshot {
+    pub fn bpm(&self) -> f64 {
+        f64::from_bits(self.bpm.load(Ordering::Relaxed))
+    }
+
+    pub fn set_bpm(&self, bpm: f64) {
+        self.bpm.store(bpm.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Returns the song position in seconds.
+    pub fn seconds(&self) -> f64 {
+        f64::from_bits(self.seconds.load(Ordering::Relaxed))
+    }
+
+    pub fn set_seconds(&self, seconds: f64) {
+        self.seconds.store(seconds.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Returns how long, in seconds, the most recently generated audio will
+    /// wait before it's heard.
+    pub fn output_latency(&self) -> f64 {
+        f64::from_bits(self.output_latency.load(Ordering::Relaxed))
+    }
+
+    pub fn set_output_latency(&self, seconds: f64) {
+        self.output_latency
+            .store(seconds.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.is_playing.load(Ordering::Relaxed)
+    }
+
+    pub fn set_is_playing(&self, is_playing: bool) {
+        self.is_playing.store(is_playing, Ordering::Relaxed);
+    }
+
+    pub fn project_sample_rate(&self) -> usize {
+        self.project_sample_rate.load(Ordering::Relaxed)
+    }
+
+    pub fn set_project_sample_rate(&self, sample_rate: usize) {
+        self.project_sample_rate
+            .store(sample_rate, Ordering::Relaxed);
+    }
+
+    pub fn is_looping(&self) -> bool {
+        self.is_looping.load(Ordering::Relaxed)
+    }
+
+    pub fn set_is_looping(&self, is_looping: bool) {
+        self.is_looping.store(is_looping, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::atomic::AtomicBool,
+        time::{Duration, Instant},
+    };
+
+    #[derive(Debug, Default)]
+    struct ToySynth {
+        gain: f64,
+        edits_applied: usize,
+    }
+
+    #[test]
+    fn edits_apply_locally_and_remotely_in_order() {
+        let (mut sender, receiver) = edit_channel(2);
+        let mut ui_copy = ToySynth::default();
+        let mut audio_copy = ToySynth::default();
+
+        let mut recorder = EditRecorder::default();
+        for gain in [0.1, 0.2, 0.3, 0.4] {
+            recorder.apply(&mut ui_copy, move |s: &mut ToySynth| s.gain = gain);
+        }
+        assert_eq!(ui_copy.gain, 0.4);
+        for edit in recorder.take() {
+            sender.send(edit);
+        }
+        assert!(recorder.is_empty());
+
+        // Only two fit; the rest wait their turn.
+        assert_eq!(sender.backlog_len(), 2);
+        assert_eq!(receiver.apply_all(&mut audio_copy), 2);
+        assert_eq!(audio_copy.gain, 0.2);
+        assert!(sender.flush());
+        assert_eq!(receiver.apply_all(&mut audio_copy), 2);
+        assert_eq!(audio_copy.gain, 0.4);
+    }
+
+    #[test]
+    fn replaying_the_log_catches_up_a_fresh_copy() {
+        let mut ui_copy = ToySynth::default();
+        let mut log = EditLog::default();
+        let mut recorder = EditRecorder::default();
+        for gain in [0.1, 0.2, 0.3] {
+            recorder.apply(&mut ui_copy, move |s: &mut ToySynth| {
+                s.gain = gain;
+                s.edits_applied += 1;
+            });
+        }
+        for edit in recorder.take() {
+            log.record(&edit);
+        }
+        assert_eq!(log.len(), 3);
+
+        // The log can be replayed more than once.
+        for _ in 0..2 {
+            let mut fresh_copy = ToySynth::default();
+            log.clone().replay(&mut fresh_copy);
+            assert_eq!(fresh_copy.gain, ui_copy.gain);
+            assert_eq!(fresh_copy.edits_applied, ui_copy.edits_applied);
+        }
+    }
+
+    #[test]
+    fn transport_snapshot_round_trips() {
+        let snapshot = TransportSnapshot::default();
+        snapshot.set_bpm(128.5);
+        snapshot.set_seconds(61.25);
+        snapshot.set_output_latency(0.0125);
+        snapshot.set_project_sample_rate(48000);
+        snapshot.set_is_looping(true);
+        assert_eq!(snapshot.bpm(), 128.5);
+        assert_eq!(snapshot.seconds(), 61.25);
+        assert_eq!(snapshot.output_latency(), 0.0125);
+        assert_eq!(snapshot.project_sample_rate(), 48000);
+        assert!(!snapshot.is_playing());
+        assert!(snapshot.is_looping());
+    }
+
+    /// Sends edits from a "UI" thread that runs flat out through the channel
+    /// and the [Scheduler](crate::schedule::Scheduler) on a "generator"
+    /// thread, the same way the app does. Checks that every edit arrives, in
+    /// order, and that no buffer takes the generator longer than it would take
+    /// to play.
+    #[test]
+    fn edits_survive_a_busy_ui_in_order() {
+        const SAMPLE_RATE: usize = 44100;
+        const BUFFER_LEN: usize = 1024;
+        const EDIT_COUNT: usize = 100_000;
+        let budget = Duration::from_secs_f64(BUFFER_LEN as f64 / SAMPLE_RATE as f64);
+
+        let (mut sender, receiver) = edit_channel::<ToySynth>(1024);
+        let is_done = Arc::new(AtomicBool::new(false));
+
+        let generator_is_done = Arc::clone(&is_done);
+        let generator = std::thread::spawn(move || {
+            let mut synth = ToySynth::default();
+            let mut scheduler = crate::schedule::Scheduler::default();
+            scheduler.reset(SAMPLE_RATE, BUFFER_LEN * 4);
+            let mut is_in_order = true;
+            let mut worst = Duration::ZERO;
+            let mut phase = 0.0f64;
+            let mut output = 0.0;
+            loop {
+                // Check before draining, so that nothing sent before the UI
+                // said it was done gets left behind.
+                let is_last_pass = generator_is_done.load(Ordering::Relaxed);
+                let started = Instant::now();
+                let mut remaining = scheduler.begin_buffer(started, BUFFER_LEN);
+                while let Some(edit) = receiver.pop() {
+                    scheduler.schedule(edit);
+                }
+                if is_last_pass {
+                    // Nothing more is coming, so let the stragglers land.
+                    scheduler.reset(SAMPLE_RATE, BUFFER_LEN * 4);
+                }
+                while remaining > 0 {
+                    let gain_before = synth.gain;
+                    scheduler.apply_due(&mut synth);
+                    is_in_order &= synth.gain >= gain_before;
+                    let len = scheduler
+                        .frames_until_next_edit()
+                        .map_or(remaining, |frames| frames.clamp(1, remaining));
+
+                    // Stand in for the synthesis a real buffer would need.
+                    for _ in 0..len {
+                        phase = (phase + 440.0 / SAMPLE_RATE as f64).fract();
+                        output += (phase * std::f64::consts::TAU).sin() * synth.gain;
+                    }
+                    scheduler.advance(len);
+                    remaining -= len;
+                }
+                worst = worst.max(started.elapsed());
+                if is_last_pass && scheduler.pending_len() == 0 {
+                    break;
+                }
+                std::thread::sleep(Duration::from_micros(500));
+            }
+            assert!(output.is_finite());
+            (synth, is_in_order, worst)
+        });
+
+        let mut ui_copy = ToySynth::default();
+        let mut edits_sent = 0;
+        while edits_sent < EDIT_COUNT {
+            let mut recorder = EditRecorder::default();
+            for _ in 0..100 {
+                let gain = edits_sent as f64;
+                recorder.apply(&mut ui_copy, move |s: &mut ToySynth| {
+                    s.gain = gain;
+                    s.edits_applied += 1;
+                });
+                edits_sent += 1;
+            }
+            for edit in recorder.take() {
+                sender.send(edit);
+            }
+            sender.flush();
+        }
+        while !sender.flush() {
+            std::hint::spin_loop();
+        }
+        is_done.store(true, Ordering::Relaxed);
+        let (synth, is_in_order, worst) = generator.join().unwrap();
+
+        assert!(is_in_order);
+        assert_eq!(synth.edits_applied, ui_copy.edits_applied);
+        assert_eq!(synth.gain, ui_copy.gain);
+        assert!(
+            worst < budget,
+            "worst buffer took {worst:?}, but plays in {budget:?}"
+        );
+    }
+}
@@ -0,0 +1,155 @@
+//! Measures how much of the real-time budget audio processing uses.
+//!
+//! Producing a buffer of audio has to take less time than playing it, or the
+//! device runs dry. [CpuLoad] tracks that ratio for the audio thread as a
+//! whole. [profile_entities()] estimates each entity's share with a synthetic
+//! benchmark, running them one at a time away from the audio thread, so that
+//! when the total gets too high we can guess which one is responsible.
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+/// How busy the audio thread is, as a fraction of the time it has. 1.0 means
+/// it's producing audio exactly as fast as the device plays it, and anything
+/// higher means underruns. The audio thread records; the UI reads.
+#[derive(Debug)]
+pub struct CpuLoad {
+    // f64 bits, like TransportSnapshot.
+    load: AtomicU64,
+    peak: AtomicU64,
+}
+impl Default for CpuLoad {
+    fn default() -> Self {
+        Self {
+            load: AtomicU64::new(0.0f64.to_bits()),
+            peak: AtomicU64::new(0.0f64.to_bits()),
+        }
+    }
+}
+impl CpuLoad {
+    /// How long it takes the smoothed load to get most of the way to a new
+    /// level. Short enough to show a spike, long enough to read.
+    const TIME_CONSTANT: Duration = Duration::from_millis(500);
+
+    /// Records that producing `budget` worth of audio took `busy`. Only one
+    /// thread should call this.
+    pub fn record(&self, busy: Duration, budget: Duration) {
+        if budget.is_zero() {
+            return;
+        }
+        let instant = busy.as_secs_f64() / budget.as_secs_f64();
+
+        // Longer buffers count for more, so the smoothing follows wall-clock
+        // time no matter how the device slices it up.
+        let weight = 1.0 - (-budget.as_secs_f64() / Self::TIME_CONSTANT.as_secs_f64()).exp();
+        let load = self.load();
+        self.load.store(
+            (load + (instant - load) * weight).to_bits(),
+            Ordering::Relaxed,
+        );
+        if instant > self.peak() {
+            self.peak.store(instant.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the recent load, smoothed.
+    pub fn load(&self) -> f64 {
+        f64::from_bits(self.load.load(Ordering::Relaxed))
+    }
+
+    /// Returns the highest load of any single buffer since the last
+    /// [CpuLoad::reset_peak()].
+    pub fn peak(&self) -> f64 {
+        f64::from_bits(self.peak.load(Ordering::Relaxed))
+    }
+
+    pub fn reset_peak(&self) {
+        self.peak.store(0.0f64.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// What one entity cost to run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntityCost {
+    pub uid: usize,
+    pub name: String,
+
+    /// How long the entity took, as a fraction of how long the audio it
+    /// produced lasts.
+    pub load: f64,
+}
+
+/// Times `run` once for each of `entities`, which are (uid, name) pairs.
+/// `run` should process `budget` worth of audio with the entity it's given,
+/// and return false if it doesn't know how, in which case that entity is left
+/// out. Returns the costs, most expensive first.
+pub fn profile_entities(
+    entities: impl IntoIterator<Item = (usize, String)>,
+    budget: Duration,
+    mut run: impl FnMut(usize) -> bool,
+) -> Vec<EntityCost> {
+    let mut costs: Vec<EntityCost> = entities
+        .into_iter()
+        .filter_map(|(uid, name)| {
+            let start = Instant::now();
+            run(uid).then(|| EntityCost {
+                uid,
+                name,
+                load: start.elapsed().as_secs_f64() / budget.as_secs_f64(),
+            })
+        })
+        .collect();
+    costs.sort_by(|a, b| b.load.total_cmp(&a.load));
+    costs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_settles_on_the_busy_fraction() {
+        let load = CpuLoad::default();
+        let budget = Duration::from_millis(10);
+        for _ in 0..500 {
+            load.record(Duration::from_millis(5), budget);
+        }
+        assert!((load.load() - 0.5).abs() < 1e-3, "{}", load.load());
+        assert!((load.peak() - 0.5).abs() < 1e-9);
+
+        // One slow buffer barely moves the average, but the peak remembers.
+        load.record(Duration::from_millis(30), budget);
+        assert!(load.load() < 0.6, "{}", load.load());
+        assert!((load.peak() - 3.0).abs() < 1e-9);
+
+        load.reset_peak();
+        assert_eq!(load.peak(), 0.0);
+        load.record(Duration::from_millis(2), budget);
+        assert!((load.peak() - 0.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn entities_are_ranked_by_cost() {
+        let entities = vec![
+            (1, "cheap".to_string()),
+            (2, "expensive".to_string()),
+            (3, "mystery".to_string()),
+        ];
+        let budget = Duration::from_millis(100);
+        let costs = profile_entities(entities, budget, |uid| match uid {
+            1 => true,
+            2 => {
+                std::thread::sleep(Duration::from_millis(20));
+                true
+            }
+            _ => false,
+        });
+        let uids: Vec<usize> = costs.iter().map(|cost| cost.uid).collect();
+        assert_eq!(uids, vec![2, 1]);
+        assert_eq!(costs[0].name, "expensive");
+        assert!(costs[0].load >= 0.2, "{}", costs[0].load);
+        assert!(costs[1].load < costs[0].load);
+    }
+}
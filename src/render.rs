@@ -0,0 +1,299 @@
+use crate::{
+    meter::{Meter, MeterReading, MeterSnapshot},
+    resample::Resampler,
+};
+use groove_core::{
+    traits::{Performs, Resets},
+    SampleType, StereoSample, SAMPLE_BUFFER_SIZE,
+};
+use groove_orchestration::Orchestrator;
+use groove_settings::SongSettings;
+use hound::{WavSpec, WavWriter};
+use std::{
+    io::{Seek, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+use strum_macros::{Display, EnumIter};
+
+/// The sample formats that a render can write.
+#[derive(Clone, Copy, Debug, Default, Display, EnumIter, PartialEq, Eq)]
+pub enum WavFormat {
+    #[strum(serialize = "16-bit")]
+    Int16,
+    #[default]
+    #[strum(serialize = "24-bit")]
+    Int24,
+    #[strum(serialize = "32-bit float")]
+    Float32,
+}
+impl WavFormat {
+    fn spec(&self, sample_rate: usize) -> WavSpec {
+        let (bits_per_sample, sample_format) = match self {
+            WavFormat::Int16 => (16, hound::SampleFormat::Int),
+            WavFormat::Int24 => (24, hound::SampleFormat::Int),
+            WavFormat::Float32 => (32, hound::SampleFormat::Float),
+        };
+        WavSpec {
+            channels: 2,
+            sample_rate: sample_rate as u32,
+            bits_per_sample,
+            sample_format,
+        }
+    }
+
+    /// Writes a single channel's value, clamped to [-1.0, 1.0] and converted
+    /// to this format.
+    fn write_sample<W>(&self, writer: &mut WavWriter<W>, value: SampleType) -> hound::Result<()>
+    where
+        W: Write + Seek,
+    {
+        let value = value.clamp(-1.0, 1.0);
+        match self {
+            WavFormat::Int16 => writer.write_sample((value * i16::MAX as SampleType) as i16),
+            WavFormat::Int24 => writer.write_sample((value * 8_388_607.0) as i32),
+            WavFormat::Float32 => writer.write_sample(value as f32),
+        }
+    }
+}
+
+/// The sample rate a project runs at if its file doesn't say otherwise.
+pub const DEFAULT_PROJECT_SAMPLE_RATE: usize = 44100;
+
+/// Describes where and how to render.
+#[derive(Clone, Debug)]
+pub struct RenderSettings {
+    pub path: PathBuf,
+
+    /// The sample rate of the file. If it differs from
+    /// `project_sample_rate`, the output is resampled.
+    pub sample_rate: usize,
+
+    /// The sample rate the project runs at.
+    pub project_sample_rate: usize,
+
+    pub format: WavFormat,
+
+    // A safety net for projects that never report the end of playback, such
+    // as one that contains nothing but an LFO.
+    pub max_seconds: f64,
+}
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("render.wav"),
+            sample_rate: DEFAULT_PROJECT_SAMPLE_RATE,
+            project_sample_rate: DEFAULT_PROJECT_SAMPLE_RATE,
+            format: Default::default(),
+            max_seconds: 10.0 * 60.0,
+        }
+    }
+}
+impl RenderSettings {
+    /// The most frames a render will write before it gives up on the song
+    /// ending by itself.
+    pub fn max_frames(&self) -> usize {
+        (self.max_seconds * self.sample_rate as f64) as usize
+    }
+}
+
+/// Lets another thread watch a render and cancel it.
+#[derive(Debug, Default)]
+pub struct RenderProgress {
+    frames_rendered: AtomicUsize,
+    is_cancelled: AtomicBool,
+    meter: MeterSnapshot,
+}
+impl RenderProgress {
+    pub fn frames_rendered(&self) -> usize {
+        self.frames_rendered.load(Ordering::Relaxed)
+    }
+
+    /// Returns the levels of everything written so far. Once the render has
+    /// finished, this describes the whole file.
+    pub fn meter(&self) -> MeterReading {
+        self.meter.reading()
+    }
+
+    /// Asks the render to stop at the next opportunity.
+    pub fn cancel(&self) {
+        self.is_cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.is_cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// How a render ended, if it didn't fail.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderOutcome {
+    /// The song finished (or hit the length limit) after this many frames.
+    Finished(usize),
+
+    /// Someone called [RenderProgress::cancel()]. The partial file has been
+    /// removed.
+    Cancelled,
+}
+
+/// Reads the project at `path` and instantiates it, resolving samples and
+/// other assets relative to `assets_path`.
+pub fn load_song(path: &Path, assets_path: &Path) -> anyhow::Result<Orchestrator> {
+    let settings = SongSettings::new_from_yaml_file(&path.to_string_lossy())
+        .map_err(|err| anyhow::anyhow!("new_from_yaml: {}", err))?;
+    let assets_path = assets_path.to_path_buf();
+    settings
+        .instantiate(&assets_path, false)
+        .map_err(|err| anyhow::anyhow!("instantiate: {}", err))
+}
+
+/// Returns the sample rate stored in the project at `path`, or
+/// [DEFAULT_PROJECT_SAMPLE_RATE] if it doesn't have one. The rate lives in an
+/// optional top-level `sample-rate` key, alongside the project's other
+/// settings.
+pub fn project_sample_rate(path: &Path) -> anyhow::Result<usize> {
+    project_sample_rate_from_yaml(&std::fs::read_to_string(path)?)
+}
+
+fn project_sample_rate_from_yaml(yaml: &str) -> anyhow::Result<usize> {
+    let value = serde_yaml::from_str::<serde_yaml::Value>(yaml)?;
+    match value.get("sample-rate") {
+        None => Ok(DEFAULT_PROJECT_SAMPLE_RATE),
+        Some(rate) => rate
+            .as_u64()
+            .filter(|rate| *rate > 0)
+            .map(|rate| rate as usize)
+            .ok_or_else(|| {
+                anyhow::anyhow!("sample-rate should be a positive number, not {rate:?}")
+            }),
+    }
+}
+
+/// Writes `orchestrator`'s output to a WAV file. Unlike the realtime path in
+/// the app, this ticks the orchestrator as fast as it can, and keeps going
+/// until the orchestrator reports the end of playback.
+///
+/// The orchestrator always runs at the project's sample rate, so a render at
+/// that rate is the same no matter where it's made or what device the app
+/// happens to be playing through.
+pub fn render_to_wav(
+    orchestrator: &mut Orchestrator,
+    settings: &RenderSettings,
+    progress: &RenderProgress,
+) -> anyhow::Result<RenderOutcome> {
+    orchestrator.reset(settings.project_sample_rate);
+    orchestrator.play();
+
+    let mut writer = WavWriter::create(&settings.path, settings.format.spec(settings.sample_rate))?;
+    let mut resampler = Resampler::new(settings.project_sample_rate, settings.sample_rate);
+    let mut meter = Meter::new(settings.sample_rate);
+    let max_frames = settings.max_frames();
+    let mut samples = [StereoSample::SILENCE; SAMPLE_BUFFER_SIZE];
+    let mut output = Vec::default();
+    let mut frames_rendered = 0;
+    loop {
+        if progress.is_cancelled() {
+            drop(writer);
+            let _ = std::fs::remove_file(&settings.path);
+            return Ok(RenderOutcome::Cancelled);
+        }
+
+        let (_response, ticks_completed) = orchestrator.tick(&mut samples);
+        resampler.push(&samples[..ticks_completed]);
+        let is_finished = ticks_completed < samples.len() || frames_rendered >= max_frames;
+        if is_finished {
+            resampler.finish();
+        }
+
+        output.resize(resampler.output_frames_available(), StereoSample::SILENCE);
+        resampler.pull(&mut output);
+        write_samples(&mut writer, settings.format, &output)?;
+        meter.process(&output);
+        frames_rendered += output.len();
+        progress
+            .frames_rendered
+            .store(frames_rendered, Ordering::Relaxed);
+        progress.meter.publish(&meter.reading());
+
+        if is_finished {
+            break;
+        }
+    }
+    writer.finalize()?;
+    Ok(RenderOutcome::Finished(frames_rendered))
+}
+
+fn write_samples<W>(
+    writer: &mut WavWriter<W>,
+    format: WavFormat,
+    samples: &[StereoSample],
+) -> hound::Result<()>
+where
+    W: Write + Seek,
+{
+    for sample in samples {
+        format.write_sample(writer, sample.0 .0)?;
+        format.write_sample(writer, sample.1 .0)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use groove_core::Sample;
+    use hound::WavReader;
+    use std::io::Cursor;
+
+    fn round_trip(format: WavFormat) -> WavReader<Cursor<Vec<u8>>> {
+        let samples = [
+            StereoSample(Sample(0.5), Sample(-0.5)),
+            StereoSample(Sample(2.0), Sample(-2.0)),
+        ];
+        let mut cursor = Cursor::new(Vec::default());
+        let mut writer = WavWriter::new(&mut cursor, format.spec(48000)).unwrap();
+        write_samples(&mut writer, format, &samples).unwrap();
+        writer.finalize().unwrap();
+        cursor.set_position(0);
+        WavReader::new(cursor).unwrap()
+    }
+
+    #[test]
+    fn writes_16_bit() {
+        let mut reader = round_trip(WavFormat::Int16);
+        assert_eq!(reader.spec().bits_per_sample, 16);
+        assert_eq!(reader.spec().sample_rate, 48000);
+        let values: Vec<i16> = reader.samples().map(|s| s.unwrap()).collect();
+        assert_eq!(values, vec![16383, -16383, i16::MAX, -i16::MAX]);
+    }
+
+    #[test]
+    fn writes_24_bit() {
+        let mut reader = round_trip(WavFormat::Int24);
+        assert_eq!(reader.spec().bits_per_sample, 24);
+        let values: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+        assert_eq!(values, vec![4_194_303, -4_194_303, 8_388_607, -8_388_607]);
+    }
+
+    #[test]
+    fn writes_32_bit_float() {
+        let mut reader = round_trip(WavFormat::Float32);
+        assert_eq!(reader.spec().sample_format, hound::SampleFormat::Float);
+        let values: Vec<f32> = reader.samples().map(|s| s.unwrap()).collect();
+        assert_eq!(values, vec![0.5, -0.5, 1.0, -1.0]);
+    }
+
+    #[test]
+    fn reads_project_sample_rate() {
+        assert_eq!(
+            project_sample_rate_from_yaml("clock:\n  bpm: 128.0\n").unwrap(),
+            DEFAULT_PROJECT_SAMPLE_RATE
+        );
+        assert_eq!(
+            project_sample_rate_from_yaml("sample-rate: 48000\nclock:\n  bpm: 128.0\n").unwrap(),
+            48000
+        );
+        assert!(project_sample_rate_from_yaml("sample-rate: fast\n").is_err());
+        assert!(project_sample_rate_from_yaml("sample-rate: 0\n").is_err());
+    }
+}
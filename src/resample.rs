@@ -0,0 +1,372 @@
+//! Converts a stream of frames from one sample rate to another, so that a
+//! project always runs at its own rate no matter what the audio device wants.
+//!
+//! This is a band-limited interpolator: each output frame is a sum of nearby
+//! input frames weighted by a Kaiser-windowed sinc. When the two rates match,
+//! frames pass through untouched.
+
+use groove_core::{Sample, StereoSample};
+use std::{collections::VecDeque, fmt::Debug, time::Duration};
+
+/// Streams frames from `from_rate` to `to_rate`. Callers ask how much input
+/// the next batch of output needs, [push()](Resampler::push) that much, then
+/// [pull()](Resampler::pull) the output.
+pub struct Resampler {
+    from_rate: usize,
+    to_rate: usize,
+
+    // Input frames per output frame.
+    step: f64,
+
+    // How far the filter reaches on either side of an output frame, in input
+    // frames.
+    reach: usize,
+
+    // The filter's cutoff as a fraction of the input Nyquist frequency.
+    cutoff: f64,
+
+    // One side of the filter kernel, sampled KERNEL_RESOLUTION times per zero
+    // crossing.
+    kernel: Vec<f64>,
+
+    // Input frames that haven't yet fallen out of the filter's reach.
+    history: VecDeque<StereoSample>,
+
+    // Where the next output frame falls, in input frames from the front of
+    // `history`.
+    position: f64,
+}
+impl Debug for Resampler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Resampler")
+            .field("from_rate", &self.from_rate)
+            .field("to_rate", &self.to_rate)
+            .field("history", &self.history.len())
+            .field("position", &self.position)
+            .finish()
+    }
+}
+impl Resampler {
+    /// How many zero crossings of the sinc the filter keeps on each side.
+    const ZERO_CROSSINGS: usize = 32;
+
+    /// How finely the kernel table is sampled between zero crossings.
+    const KERNEL_RESOLUTION: usize = 512;
+
+    /// Where the filter starts rolling off, relative to the lower of the two
+    /// Nyquist frequencies. Leaving a little room keeps the transition band
+    /// clear of aliasing.
+    const ROLLOFF: f64 = 0.95;
+
+    /// Shape of the Kaiser window. Around 9 puts the stopband below -90dB.
+    const KAISER_BETA: f64 = 9.0;
+
+    pub fn new(from_rate: usize, to_rate: usize) -> Self {
+        let step = from_rate as f64 / to_rate as f64;
+        let cutoff = Self::ROLLOFF * (to_rate as f64 / from_rate as f64).min(1.0);
+        let reach = if from_rate == to_rate {
+            0
+        } else {
+            (Self::ZERO_CROSSINGS as f64 / cutoff).ceil() as usize
+        };
+        let mut r = Self {
+            from_rate,
+            to_rate,
+            step,
+            reach,
+            cutoff,
+            kernel: Self::make_kernel(),
+            history: VecDeque::default(),
+            position: 0.0,
+        };
+        r.reset();
+        r
+    }
+
+    pub fn from_rate(&self) -> usize {
+        self.from_rate
+    }
+
+    pub fn to_rate(&self) -> usize {
+        self.to_rate
+    }
+
+    /// Returns true if frames go through unchanged.
+    pub fn is_passthrough(&self) -> bool {
+        self.from_rate == self.to_rate
+    }
+
+    /// Forgets everything pushed so far.
+    pub fn reset(&mut self) {
+        // Start as if the filter has been listening to silence, so that the
+        // first output frame lines up with the first input frame.
+        self.history.clear();
+        self.history.resize(self.reach, StereoSample::SILENCE);
+        self.position = self.reach as f64;
+    }
+
+    /// Returns how long the filter delays its input.
+    pub fn delay(&self) -> Duration {
+        Duration::from_secs_f64(self.reach as f64 / self.from_rate as f64)
+    }
+
+    /// Returns how many more input frames must be pushed before `frames`
+    /// output frames can be pulled.
+    pub fn input_frames_needed(&self, frames: usize) -> usize {
+        if frames == 0 {
+            return 0;
+        }
+        let required = if self.is_passthrough() {
+            frames
+        } else {
+            let last = self.position + (frames - 1) as f64 * self.step;
+            last.floor() as usize + self.reach + 1
+        };
+        required.saturating_sub(self.history.len())
+    }
+
+    pub fn push(&mut self, frames: &[StereoSample]) {
+        self.history.extend(frames.iter().copied());
+    }
+
+    /// Pushes enough silence to let everything already pushed come out the
+    /// other side. Call this when the input has ended.
+    pub fn finish(&mut self) {
+        self.history
+            .resize(self.history.len() + self.reach, StereoSample::SILENCE);
+    }
+
+    /// Returns how many output frames can be pulled without pushing more.
+    pub fn output_frames_available(&self) -> usize {
+        if self.is_passthrough() {
+            return self.history.len();
+        }
+        let limit = self.history.len() as f64 - self.reach as f64;
+        if limit <= self.position {
+            return 0;
+        }
+        let mut frames = ((limit - self.position) / self.step).ceil() as usize;
+        while frames > 0 && self.input_frames_needed(frames) > 0 {
+            frames -= 1;
+        }
+        frames
+    }
+
+    /// Fills `output` with resampled frames. Call
+    /// [input_frames_needed()](Resampler::input_frames_needed) first; any
+    /// frames that there wasn't enough input for are left silent.
+    pub fn pull(&mut self, output: &mut [StereoSample]) {
+        if self.is_passthrough() {
+            for frame in output.iter_mut() {
+                *frame = self.history.pop_front().unwrap_or(StereoSample::SILENCE);
+            }
+            return;
+        }
+
+        for frame in output.iter_mut() {
+            if self.position.floor() as usize + self.reach >= self.history.len() {
+                *frame = StereoSample::SILENCE;
+                continue;
+            }
+            *frame = self.interpolate(self.position);
+            self.position += self.step;
+        }
+
+        // Drop whatever the next output frame can no longer reach.
+        let stale = (self.position.floor() as usize)
+            .saturating_sub(self.reach)
+            .min(self.history.len());
+        self.history.drain(..stale);
+        self.position -= stale as f64;
+    }
+
+    fn interpolate(&self, position: f64) -> StereoSample {
+        let center = position.floor() as usize;
+        let first = center + 1 - self.reach;
+        let last = center + self.reach;
+        let (mut left, mut right) = (0.0, 0.0);
+        for (index, frame) in self.history.range(first..=last).enumerate() {
+            let distance = (position - (first + index) as f64) * self.cutoff;
+            let weight = self.kernel_at(distance);
+            left += frame.0 .0 * weight;
+            right += frame.1 .0 * weight;
+        }
+        StereoSample(Sample(left * self.cutoff), Sample(right * self.cutoff))
+    }
+
+    /// Looks up the kernel `distance` zero crossings from its center.
+    fn kernel_at(&self, distance: f64) -> f64 {
+        let index = distance.abs() * Self::KERNEL_RESOLUTION as f64;
+        let whole = index.floor() as usize;
+        if whole + 1 >= self.kernel.len() {
+            return 0.0;
+        }
+        let fraction = index - whole as f64;
+        self.kernel[whole] * (1.0 - fraction) + self.kernel[whole + 1] * fraction
+    }
+
+    fn make_kernel() -> Vec<f64> {
+        let len = Self::ZERO_CROSSINGS * Self::KERNEL_RESOLUTION + 1;
+        let i0_beta = Self::bessel_i0(Self::KAISER_BETA);
+        (0..len)
+            .map(|i| {
+                let x = i as f64 / Self::KERNEL_RESOLUTION as f64;
+                let sinc = if i == 0 {
+                    1.0
+                } else {
+                    (std::f64::consts::PI * x).sin() / (std::f64::consts::PI * x)
+                };
+                let ratio = x / Self::ZERO_CROSSINGS as f64;
+                let window =
+                    Self::bessel_i0(Self::KAISER_BETA * (1.0 - ratio * ratio).max(0.0).sqrt())
+                        / i0_beta;
+                sinc * window
+            })
+            .collect()
+    }
+
+    /// The zeroth-order modified Bessel function of the first kind, which
+    /// defines the Kaiser window.
+    fn bessel_i0(x: f64) -> f64 {
+        let mut sum = 1.0;
+        let mut term = 1.0;
+        let half_x = x / 2.0;
+        for k in 1..50 {
+            term *= (half_x / k as f64) * (half_x / k as f64);
+            sum += term;
+            if term < sum * 1e-16 {
+                break;
+            }
+        }
+        sum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f64, sample_rate: usize, len: usize) -> Vec<StereoSample> {
+        (0..len)
+            .map(|i| {
+                let value =
+                    (2.0 * std::f64::consts::PI * frequency * i as f64 / sample_rate as f64).sin();
+                StereoSample(Sample(value), Sample(-value))
+            })
+            .collect()
+    }
+
+    /// Runs `input` through `resampler` in uneven batches, the way the audio
+    /// thread does.
+    fn run(resampler: &mut Resampler, input: &[StereoSample], output_len: usize) -> Vec<f64> {
+        let mut input = input.iter().copied();
+        let mut output = Vec::default();
+        let mut batch = [StereoSample::SILENCE; 100];
+        let mut sizes = [17, 64, 1, 100, 33].iter().cycle();
+        while output.len() < output_len {
+            let len = (*sizes.next().unwrap()).min(output_len - output.len());
+            let needed: Vec<StereoSample> = input
+                .by_ref()
+                .take(resampler.input_frames_needed(len))
+                .collect();
+            resampler.push(&needed);
+            resampler.pull(&mut batch[..len]);
+            for frame in &batch[..len] {
+                assert_eq!(frame.0 .0, -frame.1 .0);
+                output.push(frame.0 .0);
+            }
+        }
+        output
+    }
+
+    #[test]
+    fn matching_rates_pass_through_untouched() {
+        let mut resampler = Resampler::new(48000, 48000);
+        assert!(resampler.is_passthrough());
+        assert_eq!(resampler.delay(), Duration::ZERO);
+        let input = sine(440.0, 48000, 1000);
+        let output = run(&mut resampler, &input, input.len());
+        let expected: Vec<f64> = input.iter().map(|frame| frame.0 .0).collect();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn converts_rates_accurately() {
+        for (from_rate, to_rate) in [(44100, 48000), (48000, 44100), (96000, 44100)] {
+            let mut resampler = Resampler::new(from_rate, to_rate);
+            let input = sine(1000.0, from_rate, from_rate / 2);
+            let output = run(&mut resampler, &input, to_rate / 4);
+            let expected = sine(1000.0, to_rate, output.len());
+
+            // Skip the part where the filter is still filling up with input.
+            let settled = (resampler.reach as f64 / resampler.step).ceil() as usize;
+            let max_error = output
+                .iter()
+                .zip(expected.iter())
+                .skip(settled)
+                .map(|(actual, expected)| (actual - expected.0 .0).abs())
+                .fold(0.0, f64::max);
+            assert!(
+                max_error < 1e-3,
+                "{from_rate} -> {to_rate}: max error {max_error}"
+            );
+        }
+    }
+
+    #[test]
+    fn downsampling_removes_what_no_longer_fits() {
+        let mut resampler = Resampler::new(48000, 22050);
+
+        // Well above the new Nyquist frequency of 11025 Hz.
+        let input = sine(16000.0, 48000, 48000);
+        let output = run(&mut resampler, &input, 11025);
+
+        // The tone starts abruptly, which isn't band-limited, so skip that.
+        let settled = &output[resampler.reach..];
+        let rms = (settled.iter().map(|v| v * v).sum::<f64>() / settled.len() as f64).sqrt();
+        assert!(rms < 1e-3, "rms {rms}");
+    }
+
+    #[test]
+    fn reports_what_it_can_produce() {
+        for (from_rate, to_rate) in [(44100, 44100), (44100, 48000), (48000, 44100)] {
+            let mut resampler = Resampler::new(from_rate, to_rate);
+            let input = sine(1000.0, from_rate, from_rate / 10);
+            let mut output = Vec::default();
+            for chunk in input.chunks(64) {
+                resampler.push(chunk);
+                let mut batch = vec![StereoSample::SILENCE; resampler.output_frames_available()];
+                resampler.pull(&mut batch);
+                output.extend(batch);
+                assert_eq!(resampler.output_frames_available(), 0);
+            }
+            resampler.finish();
+            let mut batch = vec![StereoSample::SILENCE; resampler.output_frames_available()];
+            resampler.pull(&mut batch);
+            output.extend(batch);
+
+            // Everything comes out, give or take the partial frame at the end.
+            let expected = input.len() * to_rate / from_rate;
+            assert!(
+                output.len() >= expected && output.len() <= expected + 1,
+                "{from_rate} -> {to_rate}: {} frames, expected {expected}",
+                output.len()
+            );
+        }
+    }
+
+    #[test]
+    fn consumes_input_at_the_right_rate() {
+        let mut resampler = Resampler::new(44100, 48000);
+        let mut consumed = 0;
+        let mut batch = [StereoSample::SILENCE; 480];
+        for _ in 0..100 {
+            let needed = resampler.input_frames_needed(batch.len());
+            consumed += needed;
+            resampler.push(&vec![StereoSample::SILENCE; needed]);
+            resampler.pull(&mut batch);
+        }
+        let expected = 48000 * 44100 / 48000;
+        assert!(consumed >= expected && consumed <= expected + 2 * resampler.reach);
+    }
+}
@@ -0,0 +1,299 @@
+//! Decides exactly how much audio to generate, and where in it each edit from
+//! the UI belongs.
+//!
+//! The audio interface asks for more samples whenever its queue has room, and
+//! tells us when it asked. Everything already in the queue will be heard
+//! before whatever we generate next, so from the request we can work out when
+//! each upcoming frame will reach the speakers. An edit the UI made at some
+//! moment is then placed on the frame that will be heard a fixed delay after
+//! that moment. The delay is the longest the queue can ever hold, which means
+//! every edit hears the same latency no matter how full the queue happened to
+//! be or how long the UI took to send it.
+
+use crate::params::{Edit, TimedEdit};
+use std::{
+    collections::VecDeque,
+    fmt::Debug,
+    time::{Duration, Instant},
+};
+
+/// Tracks the position of the audio thread's output, and holds edits until
+/// the frame that they belong on.
+pub struct Scheduler<T> {
+    sample_rate: usize,
+
+    // The position of the next frame to be generated, counting from the last
+    // reset.
+    frames_generated: usize,
+
+    // How many frames the audio interface's queue can hold.
+    capacity: usize,
+
+    // When the next frame to be generated will be heard, as estimated at the
+    // most recent request for audio.
+    next_frame_heard_at: Option<Instant>,
+
+    // How long the most recently generated frame will wait before it's heard.
+    latency: Duration,
+
+    // Edits waiting for their frame, in the order they arrived, which is
+    // also frame order.
+    pending: VecDeque<(usize, Edit<T>)>,
+}
+impl<T> Debug for Scheduler<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Scheduler")
+            .field("sample_rate", &self.sample_rate)
+            .field("frames_generated", &self.frames_generated)
+            .field("capacity", &self.capacity)
+            .field("latency", &self.latency)
+            .field("pending", &self.pending.len())
+            .finish()
+    }
+}
+impl<T> Default for Scheduler<T> {
+    fn default() -> Self {
+        Self {
+            sample_rate: 44100,
+            frames_generated: Default::default(),
+            capacity: Default::default(),
+            next_frame_heard_at: Default::default(),
+            latency: Default::default(),
+            pending: Default::default(),
+        }
+    }
+}
+impl<T> Scheduler<T> {
+    /// Starts over with a new audio queue. Any edits still waiting become due
+    /// right away, since the frames they were waiting for will never come.
+    pub fn reset(&mut self, sample_rate: usize, capacity: usize) {
+        self.sample_rate = sample_rate;
+        self.capacity = capacity;
+        self.frames_generated = 0;
+        self.next_frame_heard_at = None;
+        self.latency = Duration::ZERO;
+        for (frame, _) in self.pending.iter_mut() {
+            *frame = 0;
+        }
+    }
+
+    /// Handles a request, made at `when`, for `requested` frames, and returns
+    /// how many frames to generate.
+    pub fn begin_buffer(&mut self, when: Instant, requested: usize) -> usize {
+        let requested = requested.min(self.capacity);
+        let queued = self.capacity - requested;
+        self.latency = self.frames_to_duration(queued);
+        self.next_frame_heard_at = Some(when + self.latency);
+        requested
+    }
+
+    /// Holds onto `edit` until the frame it belongs on. Edits always apply in
+    /// the order they were scheduled, even if that means bending their
+    /// timing: one that belongs before an edit scheduled earlier waits for
+    /// it, and one that isn't tied to any moment brings everything scheduled
+    /// before it forward, so that it can take effect right away without
+    /// overtaking them.
+    pub fn schedule(&mut self, edit: TimedEdit<T>) {
+        let frame = match edit.when {
+            Some(when) => {
+                let last = self.pending.back().map_or(0, |(frame, _)| *frame);
+                self.frame_for(when).max(last)
+            }
+            None => {
+                for (frame, _) in self.pending.iter_mut() {
+                    *frame = self.frames_generated;
+                }
+                self.frames_generated
+            }
+        };
+        self.pending.push_back((frame, edit.edit));
+    }
+
+    /// Returns the frame that an edit made at `when` should land on. An edit
+    /// that has already missed its frame lands on the next one generated.
+    pub fn frame_for(&self, when: Instant) -> usize {
+        let Some(next_frame_heard_at) = self.next_frame_heard_at else {
+            return self.frames_generated;
+        };
+        let heard_at = when + self.frames_to_duration(self.capacity);
+        let offset = heard_at
+            .checked_duration_since(next_frame_heard_at)
+            .map_or(0, |d| {
+                (d.as_secs_f64() * self.sample_rate as f64).round() as usize
+            });
+        self.frames_generated + offset
+    }
+
+    /// Applies every edit due on or before the next frame to be generated,
+    /// and returns how many there were.
+    pub fn apply_due(&mut self, target: &mut T) -> usize {
+        let mut count = 0;
+        while let Some((frame, _)) = self.pending.front() {
+            if *frame > self.frames_generated {
+                break;
+            }
+            if let Some((_, edit)) = self.pending.pop_front() {
+                edit(target);
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Returns how many frames can be generated before the next edit is due,
+    /// or None if nothing is waiting.
+    pub fn frames_until_next_edit(&self) -> Option<usize> {
+        self.pending
+            .front()
+            .map(|(frame, _)| frame.saturating_sub(self.frames_generated))
+    }
+
+    /// Records that `frames` more frames have been generated.
+    pub fn advance(&mut self, frames: usize) {
+        self.frames_generated += frames;
+    }
+
+    pub fn frames_generated(&self) -> usize {
+        self.frames_generated
+    }
+
+    /// Returns how long the most recently requested audio will wait in the
+    /// queue before it's heard. This doesn't include whatever buffering the
+    /// audio interface does on its own.
+    pub fn latency(&self) -> Duration {
+        self.latency
+    }
+
+    /// Returns how many edits are waiting for their frame.
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn frames_to_duration(&self, frames: usize) -> Duration {
+        Duration::from_secs_f64(frames as f64 / self.sample_rate as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn set(value: usize) -> TimedEdit<Vec<usize>> {
+        TimedEdit::immediate(Arc::new(move |v: &mut Vec<usize>| v.push(value)))
+    }
+
+    fn set_at(when: Instant, value: usize) -> TimedEdit<Vec<usize>> {
+        TimedEdit {
+            when: Some(when),
+            edit: Arc::new(move |v: &mut Vec<usize>| v.push(value)),
+        }
+    }
+
+    #[test]
+    fn generates_exactly_what_was_asked_for() {
+        let mut scheduler = Scheduler::<Vec<usize>>::default();
+        scheduler.reset(1000, 100);
+        let now = Instant::now();
+        assert_eq!(scheduler.begin_buffer(now, 37), 37);
+        assert_eq!(scheduler.latency(), Duration::from_millis(63));
+
+        // A confused interface can't make us overfill the queue.
+        assert_eq!(scheduler.begin_buffer(now, 150), 100);
+        assert_eq!(scheduler.latency(), Duration::ZERO);
+    }
+
+    #[test]
+    fn edits_land_a_constant_latency_after_they_happened() {
+        let mut scheduler = Scheduler::default();
+        scheduler.reset(1000, 100);
+        scheduler.advance(500);
+
+        // Half the queue is still waiting to be heard, so the next frame will
+        // be heard 50ms from now. An edit made 20ms ago should be heard 100ms
+        // after that, or 30 frames into this buffer.
+        let now = Instant::now();
+        let requested = scheduler.begin_buffer(now, 50);
+        scheduler.schedule(set_at(now - Duration::from_millis(20), 1));
+        assert_eq!(scheduler.frames_until_next_edit(), Some(30));
+
+        // One made after the request belongs in a later buffer.
+        scheduler.schedule(set_at(now + Duration::from_millis(10), 2));
+
+        let mut target = Vec::default();
+        let mut generated = 0;
+        let mut applied_at = Vec::default();
+        while generated < requested {
+            if scheduler.apply_due(&mut target) > 0 {
+                applied_at.push(scheduler.frames_generated());
+            }
+            let len = scheduler
+                .frames_until_next_edit()
+                .map_or(requested - generated, |n| n.min(requested - generated));
+            scheduler.advance(len);
+            generated += len;
+        }
+        assert_eq!(target, vec![1]);
+        assert_eq!(applied_at, vec![530]);
+        assert_eq!(scheduler.pending_len(), 1);
+        assert_eq!(scheduler.frames_until_next_edit(), Some(10));
+    }
+
+    #[test]
+    fn late_and_untimed_edits_apply_at_once_in_order() {
+        let mut scheduler = Scheduler::default();
+        scheduler.reset(1000, 100);
+        let now = Instant::now();
+        scheduler.begin_buffer(now, 100);
+        scheduler.schedule(set(1));
+        scheduler.schedule(set_at(now - Duration::from_secs(1), 2));
+        scheduler.schedule(set(3));
+        assert_eq!(scheduler.frames_until_next_edit(), Some(0));
+
+        let mut target = Vec::default();
+        assert_eq!(scheduler.apply_due(&mut target), 3);
+        assert_eq!(target, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn edits_never_overtake_each_other() {
+        let mut scheduler = Scheduler::default();
+        scheduler.reset(1000, 100);
+        let now = Instant::now();
+        scheduler.begin_buffer(now, 100);
+        scheduler.schedule(set_at(now + Duration::from_millis(50), 1));
+
+        // The estimate of when the queue will be heard can jump around, so
+        // a later edit can come out looking earlier. It waits its turn.
+        scheduler.schedule(set_at(now + Duration::from_millis(20), 2));
+        assert_eq!(scheduler.frames_until_next_edit(), Some(150));
+
+        // An untimed edit, like swapping in a new project, goes right away,
+        // and takes everything before it along.
+        scheduler.schedule(set(3));
+        assert_eq!(scheduler.frames_until_next_edit(), Some(0));
+        let mut target = Vec::default();
+        assert_eq!(scheduler.apply_due(&mut target), 3);
+        assert_eq!(target, vec![1, 2, 3]);
+
+        // And doesn't hold up anything after it.
+        scheduler.schedule(set_at(now + Duration::from_millis(10), 4));
+        assert_eq!(scheduler.frames_until_next_edit(), Some(110));
+    }
+
+    #[test]
+    fn reset_makes_waiting_edits_due() {
+        let mut scheduler = Scheduler::default();
+        scheduler.reset(1000, 100);
+        let now = Instant::now();
+        scheduler.begin_buffer(now, 10);
+        scheduler.schedule(set_at(now + Duration::from_secs(1), 1));
+        assert!(scheduler.frames_until_next_edit().unwrap() > 100);
+
+        scheduler.reset(48000, 512);
+        assert_eq!(scheduler.frames_generated(), 0);
+        let mut target = Vec::default();
+        assert_eq!(scheduler.apply_due(&mut target), 1);
+        assert_eq!(target, vec![1]);
+    }
+}
@@ -1,14 +1,39 @@
 use cpal::{
     traits::{DeviceTrait, HostTrait, StreamTrait},
-    FromSample, Sample, SizedSample, Stream, SupportedStreamConfig,
+    FromSample, HostId, Sample, SizedSample, Stream, SupportedStreamConfig,
+    SupportedStreamConfigRange,
 };
 use crossbeam::queue::ArrayQueue;
-use crossbeam_channel::{unbounded, Receiver, Sender};
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, Sender};
 use groove_core::StereoSample;
-use std::{fmt::Debug, result::Result::Ok, sync::Arc, thread::JoinHandle, time::Instant};
+use std::{
+    fmt::Debug,
+    result::Result::Ok,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+use strum_macros::Display;
+
+pub use channels::{ChannelMap, ChannelSource};
+pub use error::AudioStreamError;
+pub use input::{AudioInput, AudioInputEvent, AudioInputStream, FileAudioInput, InputFeed};
+pub use null::{NullAudioStream, NullPacing};
+pub use stats::{AudioStats, UnderrunCounter};
+
+mod channels;
+mod error;
+mod input;
+mod null;
+mod stats;
 
 pub enum AudioInterfaceInput {
     SetBufferSize(usize),
+    SetDevice(AudioDeviceId),
+    SetChannelMap(ChannelMap),
     Play,
     Pause,
     Quit,
@@ -16,22 +41,236 @@ pub enum AudioInterfaceInput {
 
 #[derive(Clone, Debug)]
 pub enum AudioInterfaceEvent {
-    Reset(usize, AudioQueue),
+    // The stream's sample rate, the queue for the main mix, and the queue for
+    // the cue bus. The stream takes a frame from the cue queue for each one
+    // it takes from the main queue, so a producer that uses the cue bus
+    // should push one cue frame for each frame of the mix.
+    Reset(usize, AudioQueue, AudioQueue),
     NeedsAudio(Instant, usize),
+
+    // The stream ran out of samples and had to play this many frames of
+    // silence.
+    Underrun(Instant, usize),
+    Error(AudioStreamError),
+
+    // The service is trying to reconnect to an audio device after a failure.
+    // The value is the number of attempts so far, starting at 1. A successful
+    // attempt is followed by a Reset.
+    Recovering(usize),
+
+    // The device started, paused, or went away.
+    DeviceState(AudioDeviceState),
+
+    // The device that the service just connected to has this many output
+    // channels. Sent each time the service connects, before the device's
+    // state.
+    OutputChannels(usize),
     Quit,
 }
 
+/// Whether the audio device is consuming samples.
+#[derive(Clone, Copy, Debug, Default, Display, PartialEq, Eq)]
+pub enum AudioDeviceState {
+    /// The device is playing whatever is in the queue.
+    Running,
+
+    /// The device is connected, but isn't asking for audio.
+    Paused,
+
+    /// There's no working device.
+    #[default]
+    Disconnected,
+}
+
 /// The producer-consumer queue of stereo samples that the audio stream consumes.
 pub type AudioQueue = Arc<ArrayQueue<StereoSample>>;
 
+/// Waits up to `timeout` for the thread behind `handle` to exit, and returns
+/// what it returned. If it's still running when time is up, it's left to
+/// finish on its own. `name` says which thread it is in any error, as in
+/// "Render thread didn't shut down in time".
+pub fn join_within<T>(
+    handle: JoinHandle<T>,
+    name: &str,
+    timeout: Duration,
+) -> Result<T, AudioStreamError> {
+    const POLL_INTERVAL: Duration = Duration::from_millis(5);
+    let deadline = Instant::now() + timeout;
+    while !handle.is_finished() {
+        if Instant::now() >= deadline {
+            return Err(AudioStreamError::ShutdownTimedOut(name.to_string()));
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    handle.join().map_err(|payload| {
+        let message = if let Some(message) = payload.downcast_ref::<&str>() {
+            message.to_string()
+        } else if let Some(message) = payload.downcast_ref::<String>() {
+            message.clone()
+        } else {
+            String::default()
+        };
+        AudioStreamError::ThreadPanicked(name.to_string(), message)
+    })
+}
+
+/// Identifies an output device by the cpal host that owns it and the name the
+/// host reports for it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AudioDeviceId {
+    pub host: HostId,
+    pub name: String,
+}
+
+/// Describes an output device that [AudioStreamService::output_devices()]
+/// found.
+#[derive(Clone, Debug)]
+pub struct AudioDeviceDescription {
+    pub id: AudioDeviceId,
+
+    // Whether this is the host's default output device.
+    pub is_default: bool,
+
+    // The config that would be used if this device were selected.
+    pub default_config: Option<SupportedStreamConfig>,
+
+    // All the configs the device claims to support.
+    pub supported_configs: Vec<SupportedStreamConfigRange>,
+}
+
+/// Something that consumes an [AudioQueue] and asks for more audio as the
+/// queue drains. Usually this is an [AudioStream] connected to an audio
+/// interface, but it doesn't have to be.
+pub trait AudioBackend {
+    /// Returns the sample rate at which the backend consumes samples.
+    fn sample_rate(&self) -> usize;
+
+    /// Returns the capacity of the queue that the backend consumes.
+    fn buffer_size(&self) -> usize;
+
+    /// Returns how many output channels the backend has. Backends without
+    /// real output channels act like a stereo device.
+    fn channel_count(&self) -> usize {
+        2
+    }
+
+    /// Replaces the queue with a new one of the given capacity. Anything still
+    /// in the old queue is discarded. On success, sends a fresh Reset event so
+    /// that the producer starts filling the new queue.
+    fn set_buffer_size(&mut self, buffer_size: usize) -> Result<(), AudioStreamError>;
+
+    /// Changes which output channels get which parts of the mix. Backends
+    /// without real output channels ignore this.
+    fn set_channel_map(&mut self, _channel_map: ChannelMap) -> Result<(), AudioStreamError> {
+        Ok(())
+    }
+
+    /// Tells the backend to start consuming samples from the queue.
+    fn play(&self) -> Result<(), AudioStreamError>;
+
+    /// Tells the backend to stop consuming samples from the queue.
+    fn pause(&self) -> Result<(), AudioStreamError>;
+
+    /// Gives the backend a chance to clean up before the thread exits.
+    fn quit(&mut self);
+
+    /// Returns true if the backend has stopped working and should be replaced.
+    fn has_failed(&self) -> bool {
+        false
+    }
+}
+
+/// What [AudioStreamService] asks for whenever it needs a new backend.
+#[derive(Clone, Debug)]
+pub struct BackendRequest {
+    /// The device to connect to, or None for the default one.
+    pub device_id: Option<AudioDeviceId>,
+    pub buffer_size: usize,
+    pub channel_map: ChannelMap,
+}
+impl Default for BackendRequest {
+    fn default() -> Self {
+        Self {
+            device_id: None,
+            buffer_size: AudioStream::REASONABLE_BUFFER_SIZE,
+            channel_map: ChannelMap::default(),
+        }
+    }
+}
+
 pub struct AudioStreamService {
     input_sender: Sender<AudioInterfaceInput>,
     event_receiver: Receiver<AudioInterfaceEvent>, // AudioStream events
 
     handler: JoinHandle<()>, // The AudioStream thread
 }
+impl Default for AudioStreamService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl AudioStreamService {
+    /// How long [AudioStreamService::shutdown()] waits when the caller has no
+    /// particular reason to pick a different limit.
+    pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+    /// How often the service checks whether the backend has failed.
+    const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// How long the service waits after its first failed attempt to
+    /// reconnect. The wait doubles after each failure after that.
+    const FIRST_RECOVERY_DELAY: Duration = Duration::from_millis(250);
+
+    /// The longest the service waits between attempts to reconnect.
+    const MAX_RECOVERY_DELAY: Duration = Duration::from_secs(30);
+
+    /// Starts a service connected to the default output device.
     pub fn new() -> Self {
+        Self::new_with(|request, sender| {
+            AudioStream::create_stream(
+                request.device_id.as_ref(),
+                request.buffer_size,
+                &request.channel_map,
+                sender,
+            )
+            .map(|s| Box::new(s) as Box<dyn AudioBackend>)
+        })
+    }
+
+    /// Starts a service that isn't connected to any audio hardware. See
+    /// [NullAudioStream].
+    pub fn new_null(pacing: NullPacing) -> Self {
+        Self::new_with(move |request, sender| {
+            Ok(Box::new(NullAudioStream::new_with(
+                NullAudioStream::DEFAULT_SAMPLE_RATE,
+                request.buffer_size,
+                pacing,
+                sender,
+            )) as Box<dyn AudioBackend>)
+        })
+    }
+
+    /// Starts a service with whatever backend `make_backend` creates. The
+    /// backend is created on the service thread, because some of them (cpal
+    /// streams, for example) can't be moved across threads.
+    ///
+    /// The service calls `make_backend` again whenever it needs a new backend:
+    /// when the app picks a device, and when the current backend fails. In the
+    /// second case it keeps asking for the most recently selected device (or
+    /// the default one), reporting an [AudioInterfaceEvent::Error] after each
+    /// failed attempt and waiting longer before each new one.
+    ///
+    /// The service remembers whether it was last told to play or pause, and
+    /// puts every new backend in that state. It starts out playing.
+    pub fn new_with<F>(mut make_backend: F) -> Self
+    where
+        F: FnMut(
+                &BackendRequest,
+                Sender<AudioInterfaceEvent>,
+            ) -> Result<Box<dyn AudioBackend>, AudioStreamError>
+            + Send
+            + 'static,
+    {
         // Sends input from the app to the service.
         let (input_sender, input_receiver) = unbounded();
 
@@ -39,21 +278,122 @@ impl AudioStreamService {
         let (event_sender, event_receiver) = unbounded();
 
         let handler = std::thread::spawn(move || {
-            if let Ok(mut audio_stream) = AudioStream::create_default_stream(
-                AudioStream::REASONABLE_BUFFER_SIZE,
-                event_sender.clone(),
-            ) {
-                loop {
-                    if let Ok(input) = input_receiver.recv() {
-                        match input {
-                            AudioInterfaceInput::SetBufferSize(_) => todo!(),
-                            AudioInterfaceInput::Play => audio_stream.play(),
-                            AudioInterfaceInput::Pause => audio_stream.pause(),
-                            AudioInterfaceInput::Quit => {
-                                audio_stream.quit();
-                                break;
+            let mut should_run = true;
+            let mut request = BackendRequest::default();
+            let mut backend = match make_backend(&request, event_sender.clone()) {
+                Ok(backend) => {
+                    Self::connected(backend.as_ref(), should_run, &event_sender);
+                    Some(backend)
+                }
+                Err(err) => {
+                    let _ = event_sender.send(AudioInterfaceEvent::Error(err));
+                    None
+                }
+            };
+            if let Some(backend) = backend.as_ref() {
+                request.buffer_size = backend.buffer_size();
+            }
+            let mut recovery_attempts = 0;
+            let mut next_recovery = Instant::now();
+            loop {
+                let timeout = if recovery_attempts > 0 {
+                    Self::HEALTH_CHECK_INTERVAL
+                        .min(next_recovery.saturating_duration_since(Instant::now()))
+                } else {
+                    Self::HEALTH_CHECK_INTERVAL
+                };
+                match input_receiver.recv_timeout(timeout) {
+                    Ok(input) => match input {
+                        AudioInterfaceInput::SetBufferSize(new_buffer_size) => {
+                            if let Some(backend) = backend.as_mut() {
+                                match backend.set_buffer_size(new_buffer_size) {
+                                    Ok(_) => request.buffer_size = new_buffer_size,
+                                    Err(err) => {
+                                        let _ = event_sender.send(AudioInterfaceEvent::Error(err));
+                                    }
+                                }
+                            } else {
+                                request.buffer_size = new_buffer_size;
+                            }
+                        }
+                        AudioInterfaceInput::SetDevice(new_device_id) => {
+                            // As in recovery, close the old stream first. If
+                            // the new device doesn't work out, recovery
+                            // reconnects to the old one.
+                            backend = None;
+                            let new_request = BackendRequest {
+                                device_id: Some(new_device_id),
+                                ..request.clone()
+                            };
+                            match make_backend(&new_request, event_sender.clone()) {
+                                Ok(new_backend) => {
+                                    Self::connected(
+                                        new_backend.as_ref(),
+                                        should_run,
+                                        &event_sender,
+                                    );
+                                    backend = Some(new_backend);
+                                    request = new_request;
+                                }
+                                Err(err) => {
+                                    let _ = event_sender.send(AudioInterfaceEvent::Error(err));
+                                }
+                            }
+                        }
+                        AudioInterfaceInput::SetChannelMap(new_channel_map) => {
+                            if let Some(backend) = backend.as_mut() {
+                                if let Err(err) = backend.set_channel_map(new_channel_map.clone()) {
+                                    let _ = event_sender.send(AudioInterfaceEvent::Error(err));
+                                    continue;
+                                }
+                            }
+                            request.channel_map = new_channel_map;
+                        }
+                        AudioInterfaceInput::Play | AudioInterfaceInput::Pause => {
+                            should_run = matches!(input, AudioInterfaceInput::Play);
+                            if let Some(backend) = backend.as_ref() {
+                                Self::apply_run_state(backend.as_ref(), should_run, &event_sender);
                             }
                         }
+                        AudioInterfaceInput::Quit => {
+                            if let Some(backend) = backend.as_mut() {
+                                backend.quit();
+                            } else {
+                                let _ = event_sender.send(AudioInterfaceEvent::Quit);
+                            }
+                            break;
+                        }
+                    },
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+
+                let needs_recovery = match backend.as_ref() {
+                    Some(backend) => backend.has_failed(),
+                    None => true,
+                };
+                if needs_recovery && Instant::now() >= next_recovery {
+                    // Drop the old stream before building its replacement, in
+                    // case the host won't open a device twice.
+                    backend = None;
+                    if recovery_attempts == 0 {
+                        let _ = event_sender.send(AudioInterfaceEvent::DeviceState(
+                            AudioDeviceState::Disconnected,
+                        ));
+                    }
+                    recovery_attempts += 1;
+                    let _ = event_sender.send(AudioInterfaceEvent::Recovering(recovery_attempts));
+                    match make_backend(&request, event_sender.clone()) {
+                        Ok(new_backend) => {
+                            Self::connected(new_backend.as_ref(), should_run, &event_sender);
+                            backend = Some(new_backend);
+                            recovery_attempts = 0;
+                        }
+                        Err(err) => {
+                            let _ = event_sender.send(AudioInterfaceEvent::Error(err));
+                            next_recovery =
+                                Instant::now() + Self::recovery_delay(recovery_attempts);
+                        }
                     }
                 }
             }
@@ -65,37 +405,140 @@ impl AudioStreamService {
         }
     }
 
+    /// Returns how long to wait before trying to reconnect again, after
+    /// `attempts` attempts in a row have failed.
+    fn recovery_delay(attempts: usize) -> Duration {
+        let doublings = attempts.saturating_sub(1).min(16) as u32;
+        Self::FIRST_RECOVERY_DELAY
+            .saturating_mul(1 << doublings)
+            .min(Self::MAX_RECOVERY_DELAY)
+    }
+
+    /// Tells the app about a backend that was just created, and puts it in
+    /// the state the app last asked for.
+    fn connected(
+        backend: &dyn AudioBackend,
+        should_run: bool,
+        event_sender: &Sender<AudioInterfaceEvent>,
+    ) {
+        let _ = event_sender.send(AudioInterfaceEvent::OutputChannels(backend.channel_count()));
+        Self::apply_run_state(backend, should_run, event_sender);
+    }
+
+    /// Plays or pauses `backend`, and tells the app how that went.
+    fn apply_run_state(
+        backend: &dyn AudioBackend,
+        should_run: bool,
+        event_sender: &Sender<AudioInterfaceEvent>,
+    ) {
+        let (result, state) = if should_run {
+            (backend.play(), AudioDeviceState::Running)
+        } else {
+            (backend.pause(), AudioDeviceState::Paused)
+        };
+        let _ = event_sender.send(match result {
+            Ok(()) => AudioInterfaceEvent::DeviceState(state),
+            Err(err) => AudioInterfaceEvent::Error(err),
+        });
+    }
+
     pub fn sender(&self) -> &Sender<AudioInterfaceInput> {
         &self.input_sender
     }
 
+    /// Tells the service to quit, if nobody has yet, and waits up to
+    /// `timeout` for its thread to exit. Any events still in the receiver are
+    /// discarded.
+    pub fn shutdown(self, timeout: Duration) -> Result<(), AudioStreamError> {
+        let _ = self.input_sender.send(AudioInterfaceInput::Quit);
+        join_within(self.handler, "Audio stream service", timeout)
+    }
+
     pub fn receiver(&self) -> &Receiver<AudioInterfaceEvent> {
         &self.event_receiver
     }
+
+    /// Returns the cpal hosts that are available on this system.
+    pub fn hosts() -> Vec<HostId> {
+        cpal::available_hosts()
+    }
+
+    /// Returns the output devices that the given host knows about, along with
+    /// the stream configs that each one supports. Devices that fail to report
+    /// their name are skipped, since we'd have no way to select them later.
+    pub fn output_devices(
+        host_id: HostId,
+    ) -> Result<Vec<AudioDeviceDescription>, AudioStreamError> {
+        let host =
+            cpal::host_from_id(host_id).map_err(|_| AudioStreamError::HostUnavailable(host_id))?;
+        let default_name = host.default_output_device().and_then(|d| d.name().ok());
+        let mut descriptions = Vec::default();
+        for device in host.output_devices()? {
+            let Ok(name) = device.name() else {
+                continue;
+            };
+            let supported_configs = match device.supported_output_configs() {
+                Ok(configs) => configs.collect(),
+                Err(_) => Vec::default(),
+            };
+            descriptions.push(AudioDeviceDescription {
+                is_default: default_name.as_ref() == Some(&name),
+                id: AudioDeviceId {
+                    host: host_id,
+                    name,
+                },
+                default_config: device.default_output_config().ok(),
+                supported_configs,
+            });
+        }
+        Ok(descriptions)
+    }
 }
 
 /// Encapsulates the connection to the audio interface.
 pub struct AudioStream {
+    // The cpal device that the stream plays through.
+    device: cpal::Device,
+
     // cpal config describing the current audio stream.
     config: SupportedStreamConfig,
 
-    // The cpal audio stream.
-    stream: Stream,
+    // The cpal audio stream. None only if rebuilding it failed, in which
+    // case the stream has also been marked as failed.
+    stream: Option<Stream>,
 
     // The queue of samples that the stream consumes.
     queue: AudioQueue,
 
+    // The cue bus, consumed in step with `queue`.
+    cue: AudioQueue,
+
+    // Which output channels get which parts of the mix.
+    channel_map: ChannelMap,
+
     // The sending half of the channel that the audio stream uses to send
     // updates to the subscription.
     sender: Sender<AudioInterfaceEvent>,
+
+    // Set by the cpal error callback if the device goes away.
+    has_failed: Arc<AtomicBool>,
+
+    // Whether the stream was last told to play. A rebuilt stream starts out
+    // in the same state.
+    is_playing: AtomicBool,
 }
 impl Debug for AudioStream {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("AudioStream")
+            .field("device", &"(skipped)")
             .field("config", &"(skipped)")
             .field("stream", &"(skipped)")
             .field("queue", &self.queue)
+            .field("cue", &self.cue)
+            .field("channel_map", &self.channel_map)
             .field("sender", &self.sender)
+            .field("has_failed", &self.has_failed)
+            .field("is_playing", &self.is_playing)
             .finish()
     }
 }
@@ -114,92 +557,186 @@ impl AudioStream {
     pub fn create_default_stream(
         buffer_size: usize,
         audio_stream_event_sender: Sender<AudioInterfaceEvent>,
-    ) -> Result<Self, ()> {
-        if let Ok((_host, device, config)) = Self::host_device_setup() {
-            let queue = Arc::new(ArrayQueue::new(buffer_size));
-            if let Ok(stream) = Self::stream_setup_for(
-                &device,
-                &config,
-                &Arc::clone(&queue),
-                audio_stream_event_sender.clone(),
-            ) {
-                let r = Self {
-                    config,
-                    stream,
-                    queue,
-                    sender: audio_stream_event_sender,
-                };
-                r.send_reset();
-                Ok(r)
-            } else {
-                Err(())
-            }
-        } else {
-            Err(())
-        }
-    }
-
-    /// Returns the sample rate of the current audio stream.
-    pub fn sample_rate(&self) -> usize {
-        let config: &cpal::StreamConfig = &self.config.clone().into();
-        config.sample_rate.0 as usize
-    }
-
-    /// Tells the audio stream to stop playing audio (which means it will also
-    /// stop consuming samples from the queue).
-    pub fn play(&self) {
-        let _ = self.stream.play();
-    }
-
-    /// Tells the audio stream to resume playing audio (and consuming samples
-    /// from the queue).
-    pub fn pause(&self) {
-        let _ = self.stream.pause();
+    ) -> Result<Self, AudioStreamError> {
+        Self::create_stream(
+            None,
+            buffer_size,
+            &ChannelMap::default(),
+            audio_stream_event_sender,
+        )
     }
 
-    /// Gives the audio stream a chance to clean up before the thread exits.
-    pub fn quit(&mut self) {
-        let _ = self.sender.send(AudioInterfaceEvent::Quit);
+    /// Like create_default_stream(), but connects to the given device if one is
+    /// specified, and routes the mix according to `channel_map`.
+    pub fn create_stream(
+        device_id: Option<&AudioDeviceId>,
+        buffer_size: usize,
+        channel_map: &ChannelMap,
+        audio_stream_event_sender: Sender<AudioInterfaceEvent>,
+    ) -> Result<Self, AudioStreamError> {
+        if buffer_size == 0 {
+            return Err(AudioStreamError::InvalidBufferSize(buffer_size));
+        }
+        let (_host, device, config) = Self::host_device_setup(device_id)?;
+        let queue = Arc::new(ArrayQueue::new(buffer_size));
+        let cue = Arc::new(ArrayQueue::new(buffer_size));
+        let has_failed = Arc::new(AtomicBool::new(false));
+        let stream = Self::stream_setup_for(
+            &device,
+            &config,
+            &queue,
+            &cue,
+            channel_map,
+            audio_stream_event_sender.clone(),
+            &has_failed,
+        )?;
+        let r = Self {
+            device,
+            config,
+            stream: Some(stream),
+            queue,
+            cue,
+            channel_map: channel_map.clone(),
+            sender: audio_stream_event_sender,
+            has_failed,
+            is_playing: AtomicBool::new(false),
+        };
+        r.send_reset();
+        Ok(r)
     }
 
-    /// Returns the default host, device, and stream config (all of which are
-    /// cpal concepts).
+    /// Returns the host, device, and stream config (all of which are cpal
+    /// concepts) for the given device, or the defaults if none is specified.
     fn host_device_setup(
-    ) -> anyhow::Result<(cpal::Host, cpal::Device, cpal::SupportedStreamConfig), anyhow::Error>
-    {
-        let host = cpal::default_host();
-        let device = host
-            .default_output_device()
-            .ok_or_else(|| anyhow::Error::msg("Default output device is not available"))?;
+        device_id: Option<&AudioDeviceId>,
+    ) -> Result<(cpal::Host, cpal::Device, cpal::SupportedStreamConfig), AudioStreamError> {
+        let (host, device) = if let Some(device_id) = device_id {
+            let host = cpal::host_from_id(device_id.host)
+                .map_err(|_| AudioStreamError::HostUnavailable(device_id.host))?;
+            let device = host
+                .output_devices()?
+                .find(|d| matches!(d.name(), Ok(name) if name == device_id.name))
+                .ok_or_else(|| AudioStreamError::DeviceNotFound(Some(device_id.name.clone())))?;
+            (host, device)
+        } else {
+            let host = cpal::default_host();
+            let device = host
+                .default_output_device()
+                .ok_or(AudioStreamError::DeviceNotFound(None))?;
+            (host, device)
+        };
         let config = device.default_output_config()?;
         Ok((host, device, config))
     }
 
     /// Creates and returns a Stream for the given device and config. The Stream
-    /// will consume the supplied ArrayQueue<f32>. This function is actually a
-    /// wrapper around the generic stream_make<T>().
+    /// will consume the supplied queues. This function is actually a wrapper
+    /// around the generic stream_make<T>().
     fn stream_setup_for(
         device: &cpal::Device,
         config: &SupportedStreamConfig,
         queue: &AudioQueue,
+        cue: &AudioQueue,
+        channel_map: &ChannelMap,
         audio_stream_event_sender: Sender<AudioInterfaceEvent>,
-    ) -> anyhow::Result<Stream, anyhow::Error> {
+        has_failed: &Arc<AtomicBool>,
+    ) -> Result<Stream, AudioStreamError> {
         let config = config.clone();
 
         match config.sample_format() {
-            cpal::SampleFormat::I8 => todo!(),
-            cpal::SampleFormat::I16 => todo!(),
-            cpal::SampleFormat::I32 => todo!(),
-            cpal::SampleFormat::I64 => todo!(),
-            cpal::SampleFormat::U8 => todo!(),
-            cpal::SampleFormat::U16 => todo!(),
-            cpal::SampleFormat::U32 => todo!(),
-            cpal::SampleFormat::U64 => todo!(),
-            cpal::SampleFormat::F32 => {
-                Self::stream_make::<f32>(&config.into(), &device, queue, audio_stream_event_sender)
-            }
-            cpal::SampleFormat::F64 => todo!(),
-            _ => todo!(),
+            cpal::SampleFormat::I8 => Self::stream_make::<i8>(
+                &config.into(),
+                device,
+                queue,
+                cue,
+                channel_map,
+                audio_stream_event_sender,
+                has_failed,
+            ),
+            cpal::SampleFormat::I16 => Self::stream_make::<i16>(
+                &config.into(),
+                device,
+                queue,
+                cue,
+                channel_map,
+                audio_stream_event_sender,
+                has_failed,
+            ),
+            cpal::SampleFormat::I32 => Self::stream_make::<i32>(
+                &config.into(),
+                device,
+                queue,
+                cue,
+                channel_map,
+                audio_stream_event_sender,
+                has_failed,
+            ),
+            cpal::SampleFormat::I64 => Self::stream_make::<i64>(
+                &config.into(),
+                device,
+                queue,
+                cue,
+                channel_map,
+                audio_stream_event_sender,
+                has_failed,
+            ),
+            cpal::SampleFormat::U8 => Self::stream_make::<u8>(
+                &config.into(),
+                device,
+                queue,
+                cue,
+                channel_map,
+                audio_stream_event_sender,
+                has_failed,
+            ),
+            cpal::SampleFormat::U16 => Self::stream_make::<u16>(
+                &config.into(),
+                device,
+                queue,
+                cue,
+                channel_map,
+                audio_stream_event_sender,
+                has_failed,
+            ),
+            cpal::SampleFormat::U32 => Self::stream_make::<u32>(
+                &config.into(),
+                device,
+                queue,
+                cue,
+                channel_map,
+                audio_stream_event_sender,
+                has_failed,
+            ),
+            cpal::SampleFormat::U64 => Self::stream_make::<u64>(
+                &config.into(),
+                device,
+                queue,
+                cue,
+                channel_map,
+                audio_stream_event_sender,
+                has_failed,
+            ),
+            cpal::SampleFormat::F32 => Self::stream_make::<f32>(
+                &config.into(),
+                device,
+                queue,
+                cue,
+                channel_map,
+                audio_stream_event_sender,
+                has_failed,
+            ),
+            cpal::SampleFormat::F64 => Self::stream_make::<f64>(
+                &config.into(),
+                device,
+                queue,
+                cue,
+                channel_map,
+                audio_stream_event_sender,
+                has_failed,
+            ),
+            sample_format => Err(AudioStreamError::UnsupportedSampleFormat(
+                sample_format.to_string(),
+            )),
         }
     }
 
@@ -208,22 +745,40 @@ impl AudioStream {
         config: &cpal::StreamConfig,
         device: &cpal::Device,
         queue: &AudioQueue,
+        cue: &AudioQueue,
+        channel_map: &ChannelMap,
         audio_stream_event_sender: Sender<AudioInterfaceEvent>,
-    ) -> Result<Stream, anyhow::Error>
+        has_failed: &Arc<AtomicBool>,
+    ) -> Result<Stream, AudioStreamError>
     where
         T: SizedSample + FromSample<f32>,
     {
-        let err_fn = |err| eprintln!("Error building output sound stream: {}", err);
+        let error_sender = audio_stream_event_sender.clone();
+        let has_failed = Arc::clone(has_failed);
+        let err_fn = move |err| {
+            let error = match err {
+                cpal::StreamError::DeviceNotAvailable => {
+                    has_failed.store(true, Ordering::Relaxed);
+                    AudioStreamError::DeviceLost
+                }
+                cpal::StreamError::BackendSpecific { err } => {
+                    AudioStreamError::Stream(err.to_string())
+                }
+            };
+            let _ = error_sender.send(AudioInterfaceEvent::Error(error));
+        };
 
-        let queue = Arc::clone(&queue);
-        let channel_count = config.channels as usize;
+        let queue = Arc::clone(queue);
+        let cue = Arc::clone(cue);
+        let channel_sources = channel_map.resolve(config.channels as usize);
         let stream = device.build_output_stream(
             config,
             move |output: &mut [T], _: &cpal::OutputCallbackInfo| {
                 Self::on_window(
                     output,
-                    channel_count,
+                    &channel_sources,
                     &queue,
+                    &cue,
                     audio_stream_event_sender.clone(),
                 )
             },
@@ -233,25 +788,46 @@ impl AudioStream {
         Ok(stream)
     }
 
-    /// cpal callback that supplies samples from the ArrayQueue<f32>, converting
-    /// them if needed to the stream's expected data type.
+    /// cpal callback that supplies samples from the queues, converting them if
+    /// needed to the stream's expected data type. `channel_sources` has one
+    /// entry for each of the device's channels. Running out of cue frames
+    /// just means a silent cue bus; only the main mix can underrun.
     fn on_window<T>(
         output: &mut [T],
-        channel_count: usize,
+        channel_sources: &[ChannelSource],
         queue: &AudioQueue,
+        cue: &AudioQueue,
         audio_stream_event_sender: Sender<AudioInterfaceEvent>,
     ) where
         T: Sample + FromSample<f32>,
     {
+        let channel_count = channel_sources.len();
+        if channel_count == 0 {
+            return;
+        }
+        let mut underrun_frames = 0;
         for frame in output.chunks_exact_mut(channel_count) {
-            let sample = queue.pop().unwrap_or_default();
-            let left = sample.0 .0 as f32;
-            let right = sample.1 .0 as f32;
-            frame[0] = T::from_sample(left);
-            if channel_count > 0 {
-                frame[1] = T::from_sample(right);
+            // Leave the cue bus alone during an underrun, so that it stays
+            // lined up with the mix.
+            let (sample, cue_sample) = match queue.pop() {
+                Some(sample) => (sample, cue.pop().unwrap_or_default()),
+                None => {
+                    underrun_frames += 1;
+                    (StereoSample::default(), StereoSample::default())
+                }
+            };
+            let main = [sample.0 .0 as f32, sample.1 .0 as f32];
+            let cue_frame = [cue_sample.0 .0 as f32, cue_sample.1 .0 as f32];
+            for (output, source) in frame.iter_mut().zip(channel_sources) {
+                *output = T::from_sample(source.select(main, cue_frame));
             }
         }
+        if underrun_frames > 0 {
+            let _ = audio_stream_event_sender.send(AudioInterfaceEvent::Underrun(
+                Instant::now(),
+                underrun_frames,
+            ));
+        }
         let capacity = queue.capacity();
         let len = queue.len();
         if len < capacity {
@@ -262,10 +838,445 @@ impl AudioStream {
         }
     }
 
+    /// Replaces the cpal stream with one that consumes `queue` (and a cue
+    /// queue of the same size) and routes according to `channel_map`. Sends
+    /// a Reset if the queue changed.
+    ///
+    /// The old stream is closed first, in case the host won't open a device
+    /// twice. If the new one can't be built or started, the stream is marked
+    /// as failed so that the service reconnects, rather than leaving nothing
+    /// playing.
+    fn rebuild(
+        &mut self,
+        queue: AudioQueue,
+        channel_map: ChannelMap,
+    ) -> Result<(), AudioStreamError> {
+        if let Some(stream) = self.stream.take() {
+            let _ = stream.pause();
+        }
+        let cue = if Arc::ptr_eq(&queue, &self.queue) {
+            Arc::clone(&self.cue)
+        } else {
+            Arc::new(ArrayQueue::new(queue.capacity()))
+        };
+        let is_playing = self.is_playing.load(Ordering::Relaxed);
+        let stream = Self::stream_setup_for(
+            &self.device,
+            &self.config,
+            &queue,
+            &cue,
+            &channel_map,
+            self.sender.clone(),
+            &self.has_failed,
+        )
+        .and_then(|stream| {
+            if is_playing {
+                stream.play()?;
+            }
+            Ok(stream)
+        })
+        .map_err(|err| {
+            self.has_failed.store(true, Ordering::Relaxed);
+            err
+        })?;
+        self.stream = Some(stream);
+        self.channel_map = channel_map;
+        if !Arc::ptr_eq(&queue, &self.queue) {
+            self.queue = queue;
+            self.cue = cue;
+            self.send_reset();
+        }
+        Ok(())
+    }
+
     fn send_reset(&self) {
         let _ = self.sender.send(AudioInterfaceEvent::Reset(
             self.sample_rate(),
             Arc::clone(&self.queue),
+            Arc::clone(&self.cue),
+        ));
+    }
+}
+
+impl AudioBackend for AudioStream {
+    fn sample_rate(&self) -> usize {
+        let config: &cpal::StreamConfig = &self.config.clone().into();
+        config.sample_rate.0 as usize
+    }
+
+    fn buffer_size(&self) -> usize {
+        self.queue.capacity()
+    }
+
+    fn channel_count(&self) -> usize {
+        self.config.channels() as usize
+    }
+
+    /// Rebuilds the cpal stream so that it consumes the new queue.
+    fn set_buffer_size(&mut self, buffer_size: usize) -> Result<(), AudioStreamError> {
+        if buffer_size == 0 {
+            return Err(AudioStreamError::InvalidBufferSize(buffer_size));
+        }
+        self.rebuild(
+            Arc::new(ArrayQueue::new(buffer_size)),
+            self.channel_map.clone(),
+        )
+    }
+
+    /// Rebuilds the cpal stream with the new routing. The queue is kept, so
+    /// this doesn't cause a Reset.
+    fn set_channel_map(&mut self, channel_map: ChannelMap) -> Result<(), AudioStreamError> {
+        self.rebuild(Arc::clone(&self.queue), channel_map)
+    }
+
+    fn play(&self) -> Result<(), AudioStreamError> {
+        self.stream
+            .as_ref()
+            .ok_or(AudioStreamError::DeviceLost)?
+            .play()?;
+        self.is_playing.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn pause(&self) -> Result<(), AudioStreamError> {
+        self.stream
+            .as_ref()
+            .ok_or(AudioStreamError::DeviceLost)?
+            .pause()?;
+        self.is_playing.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn quit(&mut self) {
+        let _ = self.sender.send(AudioInterfaceEvent::Quit);
+    }
+
+    fn has_failed(&self) -> bool {
+        self.has_failed.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use groove_core::Sample as GrooveSample;
+
+    /// Runs on_window() against an in-memory buffer of type T and checks that
+    /// the frames (0.5, -0.5), (1.0, -1.0) and (0.0, 0.25) come out as
+    /// `expected`, interleaved.
+    fn check_on_window<T>(expected: [T; 6])
+    where
+        T: Sample + FromSample<f32> + Debug + PartialEq,
+    {
+        let frames = [(0.5, -0.5), (1.0, -1.0), (0.0, 0.25)];
+        let queue: AudioQueue = Arc::new(ArrayQueue::new(8));
+        for (left, right) in frames {
+            let _ = queue.push(StereoSample(GrooveSample(left), GrooveSample(right)));
+        }
+        let (sender, receiver) = unbounded();
+
+        // One more frame than we queued, so we also cover the empty-queue case.
+        let mut output = vec![T::EQUILIBRIUM; (frames.len() + 1) * 2];
+        let stereo = [ChannelSource::Left, ChannelSource::Right];
+        let cue: AudioQueue = Arc::new(ArrayQueue::new(8));
+        AudioStream::on_window(&mut output, &stereo, &queue, &cue, sender);
+
+        assert_eq!(output[..expected.len()], expected);
+        let last = &output[expected.len()..];
+        assert_eq!(last, &[T::EQUILIBRIUM, T::EQUILIBRIUM]);
+
+        assert!(queue.is_empty());
+        match receiver.try_recv() {
+            Ok(AudioInterfaceEvent::Underrun(_, frames)) => assert_eq!(frames, 1),
+            other => panic!("expected Underrun, got {other:?}"),
+        }
+        match receiver.try_recv() {
+            Ok(AudioInterfaceEvent::NeedsAudio(_, count)) => assert_eq!(count, 8),
+            other => panic!("expected NeedsAudio, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn service_runs_with_null_backend() {
+        let service = AudioStreamService::new_null(NullPacing::AsFastAsPossible);
+        let timeout = std::time::Duration::from_secs(1);
+        let queue = match service.receiver().recv_timeout(timeout) {
+            Ok(AudioInterfaceEvent::Reset(_, queue, _)) => queue,
+            other => panic!("expected Reset, got {other:?}"),
+        };
+
+        // The backend's thread can ask for audio before the service gets
+        // around to reporting that the device is running.
+        let mut is_running = false;
+        let mut has_channels = false;
+        let mut requests = 0;
+        while requests < 10 {
+            match service.receiver().recv_timeout(timeout) {
+                Ok(AudioInterfaceEvent::NeedsAudio(_, count)) => {
+                    for _ in 0..count {
+                        let _ = queue.push(StereoSample::SILENCE);
+                    }
+                    requests += 1;
+                }
+                Ok(AudioInterfaceEvent::DeviceState(AudioDeviceState::Running)) => {
+                    is_running = true;
+                }
+                Ok(AudioInterfaceEvent::OutputChannels(count)) => {
+                    assert!(!is_running, "channel count should come first");
+                    assert_eq!(count, 2);
+                    has_channels = true;
+                }
+                other => panic!("expected NeedsAudio, got {other:?}"),
+            }
+        }
+        assert!(is_running);
+        assert!(has_channels);
+
+        let _ = service.sender().send(AudioInterfaceInput::Pause);
+        assert!(service.receiver().iter().any(|e| matches!(
+            e,
+            AudioInterfaceEvent::DeviceState(AudioDeviceState::Paused)
+        )));
+
+        let _ = service.sender().send(AudioInterfaceInput::Quit);
+        loop {
+            match service.receiver().recv_timeout(timeout) {
+                Ok(AudioInterfaceEvent::Quit) => break,
+                Ok(_) => continue,
+                Err(err) => panic!("service didn't quit: {err}"),
+            }
+        }
+    }
+
+    /// A backend that's broken from the start.
+    struct BrokenBackend;
+    impl AudioBackend for BrokenBackend {
+        fn sample_rate(&self) -> usize {
+            44100
+        }
+        fn buffer_size(&self) -> usize {
+            64
+        }
+        fn set_buffer_size(&mut self, _buffer_size: usize) -> Result<(), AudioStreamError> {
+            Ok(())
+        }
+        fn play(&self) -> Result<(), AudioStreamError> {
+            Ok(())
+        }
+        fn pause(&self) -> Result<(), AudioStreamError> {
+            Ok(())
+        }
+        fn quit(&mut self) {}
+        fn has_failed(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn recovery_backs_off() {
+        assert_eq!(
+            AudioStreamService::recovery_delay(1),
+            AudioStreamService::FIRST_RECOVERY_DELAY
+        );
+        assert_eq!(
+            AudioStreamService::recovery_delay(3),
+            AudioStreamService::FIRST_RECOVERY_DELAY * 4
+        );
+        assert_eq!(
+            AudioStreamService::recovery_delay(usize::MAX),
+            AudioStreamService::MAX_RECOVERY_DELAY
+        );
+    }
+
+    #[test]
+    fn recovery_goes_through_the_backend_factory() {
+        let requests = Arc::new(std::sync::Mutex::new(Vec::default()));
+        let service = {
+            let requests = Arc::clone(&requests);
+            AudioStreamService::new_with(move |request, _sender| {
+                let mut requests = requests.lock().unwrap();
+                requests.push((request.clone(), Instant::now()));
+                if requests.len() == 1 {
+                    Ok(Box::new(BrokenBackend) as Box<dyn AudioBackend>)
+                } else {
+                    Err(AudioStreamError::DeviceLost)
+                }
+            })
+        };
+
+        // Any input makes the service notice that the backend is broken,
+        // without waiting for the next health check.
+        let _ = service.sender().send(AudioInterfaceInput::Play);
+        let mut attempts = Vec::default();
+        while attempts.len() < 3 {
+            match service.receiver().recv_timeout(Duration::from_secs(2)) {
+                Ok(AudioInterfaceEvent::Recovering(attempt)) => attempts.push(attempt),
+                Ok(_) => continue,
+                Err(err) => panic!("service stopped trying to recover: {err}"),
+            }
+        }
+        assert_eq!(attempts, vec![1, 2, 3]);
+        assert_eq!(service.shutdown(Duration::from_secs(1)), Ok(()));
+
+        // The first backend, then one for each attempt, each asking for the
+        // buffer size that the first one ended up with.
+        let requests = requests.lock().unwrap();
+        assert!(requests.len() >= 4);
+        assert!(requests[1..]
+            .iter()
+            .all(|(r, _)| r.buffer_size == 64 && r.device_id.is_none()));
+        assert!(requests[3].1 - requests[2].1 >= AudioStreamService::recovery_delay(2));
+    }
+
+    #[test]
+    fn service_shuts_down_within_timeout() {
+        let service = AudioStreamService::new_null(NullPacing::RealTime);
+        assert!(matches!(
+            service.receiver().recv_timeout(Duration::from_secs(1)),
+            Ok(AudioInterfaceEvent::Reset(..))
         ));
+
+        let start = Instant::now();
+        assert_eq!(
+            service.shutdown(AudioStreamService::SHUTDOWN_TIMEOUT),
+            Ok(())
+        );
+        assert!(start.elapsed() < AudioStreamService::SHUTDOWN_TIMEOUT);
+    }
+
+    #[test]
+    fn join_within_reports_panics_and_timeouts() {
+        let handle = std::thread::spawn(|| 42);
+        assert_eq!(join_within(handle, "Test", Duration::from_secs(1)), Ok(42));
+
+        let handle = std::thread::spawn(|| panic!("oops"));
+        let err = join_within(handle, "Test", Duration::from_secs(1)).unwrap_err();
+        assert_eq!(
+            err,
+            AudioStreamError::ThreadPanicked("Test".to_string(), "oops".to_string())
+        );
+        assert_eq!(err.to_string(), "Test thread panicked: oops");
+
+        let handle = std::thread::spawn(|| std::thread::sleep(Duration::from_millis(500)));
+        let err = join_within(handle, "Render", Duration::from_millis(10)).unwrap_err();
+        assert_eq!(
+            err,
+            AudioStreamError::ShutdownTimedOut("Render".to_string())
+        );
+        assert_eq!(err.to_string(), "Render thread didn't shut down in time");
+    }
+
+    #[test]
+    fn on_window_downmixes_to_mono() {
+        let queue: AudioQueue = Arc::new(ArrayQueue::new(4));
+        let _ = queue.push(StereoSample(GrooveSample(0.5), GrooveSample(-0.25)));
+        let (sender, _receiver) = unbounded();
+        let mut output = [0.0f32; 1];
+        let cue: AudioQueue = Arc::new(ArrayQueue::new(4));
+        AudioStream::on_window(&mut output, &[ChannelSource::Mono], &queue, &cue, sender);
+        assert_eq!(output, [0.125]);
+    }
+
+    #[test]
+    fn on_window_routes_multichannel() {
+        let queue: AudioQueue = Arc::new(ArrayQueue::new(4));
+        let _ = queue.push(StereoSample(GrooveSample(0.5), GrooveSample(-0.5)));
+        let cue: AudioQueue = Arc::new(ArrayQueue::new(4));
+        let _ = cue.push(StereoSample(GrooveSample(0.25), GrooveSample(-0.25)));
+        let (sender, _receiver) = unbounded();
+
+        // Main mix on 1-2, the cue bus on 3-4, and nothing on 5-6.
+        let sources = ChannelMap::new(vec![
+            ChannelSource::Left,
+            ChannelSource::Right,
+            ChannelSource::CueLeft,
+            ChannelSource::CueRight,
+        ])
+        .resolve(6);
+        let mut output = [1.0f32; 6];
+        AudioStream::on_window(&mut output, &sources, &queue, &cue, sender);
+        assert_eq!(output, [0.5, -0.5, 0.25, -0.25, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn on_window_keeps_cue_in_step_through_underruns() {
+        let queue: AudioQueue = Arc::new(ArrayQueue::new(4));
+        let cue: AudioQueue = Arc::new(ArrayQueue::new(4));
+        let _ = cue.push(StereoSample(GrooveSample(0.25), GrooveSample(0.25)));
+        let (sender, _receiver) = unbounded();
+        let sources = [ChannelSource::Left, ChannelSource::CueLeft];
+
+        // The mix is late, so the cue frame waits for it.
+        let mut output = [1.0f32; 2];
+        AudioStream::on_window(&mut output, &sources, &queue, &cue, sender.clone());
+        assert_eq!(output, [0.0, 0.0]);
+        assert_eq!(cue.len(), 1);
+
+        let _ = queue.push(StereoSample(GrooveSample(0.5), GrooveSample(0.5)));
+        AudioStream::on_window(&mut output, &sources, &queue, &cue, sender);
+        assert_eq!(output, [0.5, 0.25]);
+    }
+
+    #[test]
+    fn on_window_ignores_zero_channels() {
+        let queue: AudioQueue = Arc::new(ArrayQueue::new(4));
+        let _ = queue.push(StereoSample::SILENCE);
+        let (sender, receiver) = unbounded();
+        let mut output = [0.0f32; 4];
+        let cue: AudioQueue = Arc::new(ArrayQueue::new(4));
+        AudioStream::on_window(&mut output, &[], &queue, &cue, sender);
+        assert_eq!(queue.len(), 1);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn on_window_converts_i8() {
+        check_on_window::<i8>([64, -64, 127, -128, 0, 32]);
+    }
+
+    #[test]
+    fn on_window_converts_i16() {
+        check_on_window::<i16>([16384, -16384, 32767, -32768, 0, 8192]);
+    }
+
+    #[test]
+    fn on_window_converts_i32() {
+        check_on_window::<i32>([1 << 30, -(1 << 30), i32::MAX, i32::MIN, 0, 1 << 29]);
+    }
+
+    #[test]
+    fn on_window_converts_i64() {
+        check_on_window::<i64>([1 << 62, -(1 << 62), i64::MAX, i64::MIN, 0, 1 << 61]);
+    }
+
+    #[test]
+    fn on_window_converts_u8() {
+        // Unsigned formats center on half scale.
+        check_on_window::<u8>([192, 64, 255, 0, 128, 160]);
+    }
+
+    #[test]
+    fn on_window_converts_u16() {
+        check_on_window::<u16>([49152, 16384, 65535, 0, 32768, 40960]);
+    }
+
+    #[test]
+    fn on_window_converts_u32() {
+        check_on_window::<u32>([3 << 30, 1 << 30, u32::MAX, 0, 1 << 31, 5 << 29]);
+    }
+
+    #[test]
+    fn on_window_converts_u64() {
+        check_on_window::<u64>([3 << 62, 1 << 62, u64::MAX, 0, 1 << 63, 5 << 61]);
+    }
+
+    #[test]
+    fn on_window_converts_f32() {
+        check_on_window::<f32>([0.5, -0.5, 1.0, -1.0, 0.0, 0.25]);
+    }
+
+    #[test]
+    fn on_window_converts_f64() {
+        check_on_window::<f64>([0.5, -0.5, 1.0, -1.0, 0.0, 0.25]);
     }
 }
@@ -0,0 +1,136 @@
+use std::fmt::Display;
+use strum_macros::EnumIter;
+
+/// Where an output channel gets its signal.
+#[derive(Clone, Copy, Debug, EnumIter, PartialEq, Eq)]
+pub enum ChannelSource {
+    /// The left channel of the main mix.
+    Left,
+
+    /// The right channel of the main mix.
+    Right,
+
+    /// The average of the main mix's left and right channels.
+    Mono,
+
+    /// The left channel of the cue bus, which carries things like a click
+    /// that only the performer should hear.
+    CueLeft,
+
+    /// The right channel of the cue bus.
+    CueRight,
+
+    /// Nothing.
+    Silence,
+}
+impl Display for ChannelSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ChannelSource::Left => "Left",
+            ChannelSource::Right => "Right",
+            ChannelSource::Mono => "Mono",
+            ChannelSource::CueLeft => "Cue left",
+            ChannelSource::CueRight => "Cue right",
+            ChannelSource::Silence => "Silence",
+        })
+    }
+}
+impl ChannelSource {
+    /// Picks this source's value out of a stereo frame of the main mix and
+    /// the matching frame of the cue bus.
+    pub fn select(&self, main: [f32; 2], cue: [f32; 2]) -> f32 {
+        match self {
+            ChannelSource::Left => main[0],
+            ChannelSource::Right => main[1],
+            ChannelSource::Mono => (main[0] + main[1]) / 2.0,
+            ChannelSource::CueLeft => cue[0],
+            ChannelSource::CueRight => cue[1],
+            ChannelSource::Silence => 0.0,
+        }
+    }
+}
+
+/// Says which source feeds each of the device's output channels, in order. An
+/// empty map means "whatever makes sense for the device" (see
+/// [ChannelMap::default_for()]). Channels beyond the end of a non-empty map
+/// are silent.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChannelMap(Vec<ChannelSource>);
+impl ChannelMap {
+    pub fn new(sources: Vec<ChannelSource>) -> Self {
+        Self(sources)
+    }
+
+    /// The usual mapping for a device with the given number of channels. Mono
+    /// devices get a downmix, and everything else gets the main mix on the
+    /// first two channels.
+    pub fn default_for(channel_count: usize) -> Self {
+        match channel_count {
+            0 => Self(Vec::default()),
+            1 => Self(vec![ChannelSource::Mono]),
+            _ => {
+                let mut sources = vec![ChannelSource::Silence; channel_count];
+                sources[0] = ChannelSource::Left;
+                sources[1] = ChannelSource::Right;
+                Self(sources)
+            }
+        }
+    }
+
+    pub fn sources(&self) -> &[ChannelSource] {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns exactly one source per channel for a device with the given
+    /// number of channels.
+    pub fn resolve(&self, channel_count: usize) -> Vec<ChannelSource> {
+        if self.is_empty() {
+            return Self::default_for(channel_count).0;
+        }
+        let mut sources = self.0.clone();
+        sources.resize(channel_count, ChannelSource::Silence);
+        sources
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_defaults_by_channel_count() {
+        let map = ChannelMap::default();
+        assert_eq!(map.resolve(1), vec![ChannelSource::Mono]);
+        assert_eq!(
+            map.resolve(2),
+            vec![ChannelSource::Left, ChannelSource::Right]
+        );
+        assert_eq!(
+            map.resolve(4),
+            vec![
+                ChannelSource::Left,
+                ChannelSource::Right,
+                ChannelSource::Silence,
+                ChannelSource::Silence
+            ]
+        );
+    }
+
+    #[test]
+    fn resolves_explicit_maps_to_device_width() {
+        let map = ChannelMap::new(vec![ChannelSource::Right, ChannelSource::Left]);
+        assert_eq!(map.resolve(1), vec![ChannelSource::Right]);
+        assert_eq!(
+            map.resolve(3),
+            vec![
+                ChannelSource::Right,
+                ChannelSource::Left,
+                ChannelSource::Silence
+            ]
+        );
+    }
+}
@@ -0,0 +1,119 @@
+use cpal::{
+    BuildStreamError, DefaultStreamConfigError, DevicesError, HostId, PauseStreamError,
+    PlayStreamError,
+};
+use std::fmt::Display;
+
+/// Everything that can go wrong while setting up or running an audio stream.
+/// The cpal errors that these wrap aren't [Clone], so we keep only their
+/// descriptions, which lets the errors travel inside an
+/// [AudioInterfaceEvent](super::AudioInterfaceEvent).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AudioStreamError {
+    /// The host isn't available on this system.
+    HostUnavailable(HostId),
+
+    /// The named device wasn't found, or there's no default device if the name
+    /// is None.
+    DeviceNotFound(Option<String>),
+
+    /// Like DeviceNotFound, but for input devices.
+    InputDeviceNotFound(Option<String>),
+
+    /// The host couldn't list its devices or tell us about their configs.
+    Device(String),
+
+    /// The device wants a sample format that we don't know how to produce.
+    UnsupportedSampleFormat(String),
+
+    /// cpal couldn't build the stream.
+    BuildStream(String),
+
+    /// cpal couldn't start or stop the stream.
+    PlayPause(String),
+
+    /// The stream reported a problem while it was running.
+    Stream(String),
+
+    /// The device went away while the stream was running.
+    DeviceLost,
+
+    /// The requested buffer size can't hold any samples.
+    InvalidBufferSize(usize),
+
+    /// The named thread didn't exit within the time it was given.
+    ShutdownTimedOut(String),
+
+    /// The named thread panicked. The second value is the panic message, if
+    /// there was one.
+    ThreadPanicked(String, String),
+}
+impl Display for AudioStreamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AudioStreamError::HostUnavailable(host) => {
+                write!(f, "Audio host {} is not available", host.name())
+            }
+            AudioStreamError::DeviceNotFound(Some(name)) => {
+                write!(f, "Output device {name} is not available")
+            }
+            AudioStreamError::DeviceNotFound(None) => {
+                write!(f, "Default output device is not available")
+            }
+            AudioStreamError::InputDeviceNotFound(Some(name)) => {
+                write!(f, "Input device {name} is not available")
+            }
+            AudioStreamError::InputDeviceNotFound(None) => {
+                write!(f, "Default input device is not available")
+            }
+            AudioStreamError::Device(err) => write!(f, "Couldn't query output device: {err}"),
+            AudioStreamError::UnsupportedSampleFormat(format) => {
+                write!(f, "Unsupported sample format {format}")
+            }
+            AudioStreamError::BuildStream(err) => write!(f, "Couldn't build audio stream: {err}"),
+            AudioStreamError::PlayPause(err) => {
+                write!(f, "Couldn't start or stop audio stream: {err}")
+            }
+            AudioStreamError::Stream(err) => write!(f, "Audio stream error: {err}"),
+            AudioStreamError::DeviceLost => write!(f, "Output device was disconnected"),
+            AudioStreamError::InvalidBufferSize(size) => {
+                write!(
+                    f,
+                    "Buffer size {size} is invalid; it must be greater than zero"
+                )
+            }
+            AudioStreamError::ShutdownTimedOut(thread) => {
+                write!(f, "{thread} thread didn't shut down in time")
+            }
+            AudioStreamError::ThreadPanicked(thread, message) => {
+                write!(f, "{thread} thread panicked: {message}")
+            }
+        }
+    }
+}
+impl std::error::Error for AudioStreamError {}
+impl From<DevicesError> for AudioStreamError {
+    fn from(err: DevicesError) -> Self {
+        AudioStreamError::Device(err.to_string())
+    }
+}
+impl From<DefaultStreamConfigError> for AudioStreamError {
+    fn from(err: DefaultStreamConfigError) -> Self {
+        AudioStreamError::Device(err.to_string())
+    }
+}
+impl From<BuildStreamError> for AudioStreamError {
+    fn from(err: BuildStreamError) -> Self {
+        AudioStreamError::BuildStream(err.to_string())
+    }
+}
+impl From<PlayStreamError> for AudioStreamError {
+    fn from(err: PlayStreamError) -> Self {
+        AudioStreamError::PlayPause(err.to_string())
+    }
+}
+impl From<PauseStreamError> for AudioStreamError {
+    fn from(err: PauseStreamError) -> Self {
+        AudioStreamError::PlayPause(err.to_string())
+    }
+}
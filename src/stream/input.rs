@@ -0,0 +1,626 @@
+use super::{AudioDeviceId, AudioQueue, AudioStreamError, NullPacing};
+use crate::resample::Resampler;
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    FromSample, Sample, SizedSample, Stream, SupportedStreamConfig,
+};
+use crossbeam::queue::ArrayQueue;
+use crossbeam_channel::Sender;
+use groove_core::{Sample as GrooveSample, SampleType, StereoSample};
+use std::{
+    fmt::Debug,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// Tells the consumer of an [AudioInput] what's happening to the input.
+#[derive(Clone, Debug)]
+pub enum AudioInputEvent {
+    /// This many frames were just added to the queue.
+    Captured(Instant, usize),
+
+    /// The queue was full, so this many captured frames were thrown away.
+    Overrun(Instant, usize),
+
+    Error(AudioStreamError),
+
+    /// The input has nothing more to give. Only inputs that read from a file
+    /// send this.
+    EndOfInput,
+}
+
+/// Something that captures audio into an [AudioQueue]. The consumer pops
+/// frames from [AudioInput::queue()] whenever it likes; frames that arrive
+/// while the queue is full are dropped and reported as
+/// [AudioInputEvent::Overrun].
+pub trait AudioInput {
+    /// Returns the sample rate of the captured frames.
+    fn sample_rate(&self) -> usize;
+
+    /// Returns the queue that captured frames are pushed onto.
+    fn queue(&self) -> &AudioQueue;
+
+    /// Starts capturing.
+    fn play(&self);
+
+    /// Stops capturing. Frames already in the queue stay there.
+    fn pause(&self);
+}
+
+/// The input counterpart to [AudioStream](super::AudioStream). It captures
+/// from a cpal input device and converts whatever the device produces into
+/// stereo frames. Mono devices are copied to both channels, and channels
+/// beyond the first two are ignored.
+pub struct AudioInputStream {
+    config: SupportedStreamConfig,
+
+    // The cpal stream. Dropping it stops the capture.
+    stream: Stream,
+
+    // The queue of captured frames.
+    queue: AudioQueue,
+
+    // The sending half of the channel that the stream uses to tell its owner
+    // what's going on.
+    sender: Sender<AudioInputEvent>,
+}
+impl Debug for AudioInputStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AudioInputStream")
+            .field("config", &self.config)
+            .field("queue", &self.queue)
+            .field("sender", &self.sender)
+            .finish()
+    }
+}
+impl AudioInputStream {
+    /// Starts capturing from the given device, or from the default input
+    /// device if `device_id` is None.
+    pub fn create_stream(
+        device_id: Option<&AudioDeviceId>,
+        buffer_size: usize,
+        sender: Sender<AudioInputEvent>,
+    ) -> Result<Self, AudioStreamError> {
+        if buffer_size == 0 {
+            return Err(AudioStreamError::InvalidBufferSize(buffer_size));
+        }
+        let device = Self::device_for(device_id)?;
+        let config = device.default_input_config()?;
+        let queue = Arc::new(ArrayQueue::new(buffer_size));
+        let stream = Self::stream_setup_for(&device, &config, &queue, sender.clone())?;
+        stream.play()?;
+        Ok(Self {
+            config,
+            stream,
+            queue,
+            sender,
+        })
+    }
+
+    fn device_for(device_id: Option<&AudioDeviceId>) -> Result<cpal::Device, AudioStreamError> {
+        if let Some(device_id) = device_id {
+            let host = cpal::host_from_id(device_id.host)
+                .map_err(|_| AudioStreamError::HostUnavailable(device_id.host))?;
+            host.input_devices()?
+                .find(|d| matches!(d.name(), Ok(name) if name == device_id.name))
+                .ok_or_else(|| AudioStreamError::InputDeviceNotFound(Some(device_id.name.clone())))
+        } else {
+            cpal::default_host()
+                .default_input_device()
+                .ok_or(AudioStreamError::InputDeviceNotFound(None))
+        }
+    }
+
+    /// Like AudioStream::stream_setup_for(), but for input.
+    fn stream_setup_for(
+        device: &cpal::Device,
+        config: &SupportedStreamConfig,
+        queue: &AudioQueue,
+        sender: Sender<AudioInputEvent>,
+    ) -> Result<Stream, AudioStreamError> {
+        let stream_config = config.clone().into();
+        match config.sample_format() {
+            cpal::SampleFormat::I8 => {
+                Self::stream_make::<i8>(&stream_config, device, queue, sender)
+            }
+            cpal::SampleFormat::I16 => {
+                Self::stream_make::<i16>(&stream_config, device, queue, sender)
+            }
+            cpal::SampleFormat::I32 => {
+                Self::stream_make::<i32>(&stream_config, device, queue, sender)
+            }
+            cpal::SampleFormat::I64 => {
+                Self::stream_make::<i64>(&stream_config, device, queue, sender)
+            }
+            cpal::SampleFormat::U8 => {
+                Self::stream_make::<u8>(&stream_config, device, queue, sender)
+            }
+            cpal::SampleFormat::U16 => {
+                Self::stream_make::<u16>(&stream_config, device, queue, sender)
+            }
+            cpal::SampleFormat::U32 => {
+                Self::stream_make::<u32>(&stream_config, device, queue, sender)
+            }
+            cpal::SampleFormat::U64 => {
+                Self::stream_make::<u64>(&stream_config, device, queue, sender)
+            }
+            cpal::SampleFormat::F32 => {
+                Self::stream_make::<f32>(&stream_config, device, queue, sender)
+            }
+            cpal::SampleFormat::F64 => {
+                Self::stream_make::<f64>(&stream_config, device, queue, sender)
+            }
+            sample_format => Err(AudioStreamError::UnsupportedSampleFormat(
+                sample_format.to_string(),
+            )),
+        }
+    }
+
+    fn stream_make<T>(
+        config: &cpal::StreamConfig,
+        device: &cpal::Device,
+        queue: &AudioQueue,
+        sender: Sender<AudioInputEvent>,
+    ) -> Result<Stream, AudioStreamError>
+    where
+        T: SizedSample,
+        f32: FromSample<T>,
+    {
+        let error_sender = sender.clone();
+        let err_fn = move |err| {
+            let _ = error_sender.send(AudioInputEvent::Error(AudioStreamError::Stream(format!(
+                "{err}"
+            ))));
+        };
+        let queue = Arc::clone(queue);
+        let channel_count = config.channels as usize;
+        let stream = device.build_input_stream(
+            config,
+            move |input: &[T], _: &cpal::InputCallbackInfo| {
+                Self::on_capture(input, channel_count, &queue, &sender)
+            },
+            err_fn,
+            None,
+        )?;
+        Ok(stream)
+    }
+
+    /// cpal callback that converts captured samples to stereo frames and
+    /// pushes them onto the queue.
+    fn on_capture<T>(
+        input: &[T],
+        channel_count: usize,
+        queue: &AudioQueue,
+        sender: &Sender<AudioInputEvent>,
+    ) where
+        T: Sample,
+        f32: FromSample<T>,
+    {
+        if channel_count == 0 {
+            return;
+        }
+        let frames = input.chunks_exact(channel_count).map(|frame| {
+            let left = f32::from_sample(frame[0]);
+            let right = frame.get(1).map_or(left, |s| f32::from_sample(*s));
+            StereoSample(
+                GrooveSample(left as SampleType),
+                GrooveSample(right as SampleType),
+            )
+        });
+        push_frames(frames, queue, sender);
+    }
+}
+impl AudioInput for AudioInputStream {
+    fn sample_rate(&self) -> usize {
+        self.config.sample_rate().0 as usize
+    }
+
+    fn queue(&self) -> &AudioQueue {
+        &self.queue
+    }
+
+    fn play(&self) {
+        if let Err(err) = self.stream.play() {
+            let _ = self.sender.send(AudioInputEvent::Error(err.into()));
+        }
+    }
+
+    fn pause(&self) {
+        if let Err(err) = self.stream.pause() {
+            let _ = self.sender.send(AudioInputEvent::Error(err.into()));
+        }
+    }
+}
+
+/// An [AudioInput] that plays back a WAV file instead of capturing from a
+/// device. It's meant for tests and for working without a microphone.
+pub struct FileAudioInput {
+    sample_rate: usize,
+    queue: AudioQueue,
+    is_playing: Arc<AtomicBool>,
+    should_stop: Arc<AtomicBool>,
+    handler: Option<JoinHandle<()>>,
+}
+impl Debug for FileAudioInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FileAudioInput")
+            .field("sample_rate", &self.sample_rate)
+            .field("queue", &self.queue)
+            .finish()
+    }
+}
+impl FileAudioInput {
+    /// How many frames the producer thread pushes on each pass.
+    const FRAMES_PER_CALLBACK: usize = 512;
+
+    /// Reads the WAV file at `path` and starts feeding it into a new queue,
+    /// paced according to `pacing`. The whole file is read up front so that
+    /// the producer thread never touches the disk.
+    pub fn new_with(
+        path: &Path,
+        buffer_size: usize,
+        pacing: NullPacing,
+        sender: Sender<AudioInputEvent>,
+    ) -> anyhow::Result<Self> {
+        if buffer_size == 0 {
+            return Err(AudioStreamError::InvalidBufferSize(buffer_size).into());
+        }
+        let mut reader = hound::WavReader::open(path)?;
+        let spec = reader.spec();
+        let values: Vec<f32> = match spec.sample_format {
+            hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+            hound::SampleFormat::Int => {
+                let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .samples::<i32>()
+                    .map(|s| s.map(|s| s as f32 / scale))
+                    .collect::<Result<_, _>>()?
+            }
+        };
+        Ok(Self::new_from_samples(
+            Self::frames_from_interleaved(&values, spec.channels as usize),
+            spec.sample_rate as usize,
+            buffer_size,
+            pacing,
+            sender,
+        ))
+    }
+
+    /// Like new_with(), but plays back frames that are already in memory.
+    pub fn new_from_samples(
+        frames: Vec<StereoSample>,
+        sample_rate: usize,
+        buffer_size: usize,
+        pacing: NullPacing,
+        sender: Sender<AudioInputEvent>,
+    ) -> Self {
+        let mut r = Self {
+            sample_rate,
+            queue: Arc::new(ArrayQueue::new(buffer_size)),
+            is_playing: Arc::new(AtomicBool::new(true)),
+            should_stop: Default::default(),
+            handler: None,
+        };
+        r.start_producer(frames, pacing, sender);
+        r
+    }
+
+    fn frames_from_interleaved(values: &[f32], channel_count: usize) -> Vec<StereoSample> {
+        if channel_count == 0 {
+            return Vec::default();
+        }
+        values
+            .chunks_exact(channel_count)
+            .map(|frame| {
+                let left = frame[0];
+                let right = frame.get(1).copied().unwrap_or(left);
+                StereoSample(
+                    GrooveSample(left as SampleType),
+                    GrooveSample(right as SampleType),
+                )
+            })
+            .collect()
+    }
+
+    fn start_producer(
+        &mut self,
+        frames: Vec<StereoSample>,
+        pacing: NullPacing,
+        sender: Sender<AudioInputEvent>,
+    ) {
+        let queue = Arc::clone(&self.queue);
+        let is_playing = Arc::clone(&self.is_playing);
+        let should_stop = Arc::clone(&self.should_stop);
+        let period =
+            Duration::from_secs_f64(Self::FRAMES_PER_CALLBACK as f64 / self.sample_rate as f64);
+
+        self.handler = Some(std::thread::spawn(move || {
+            let mut deadline = Instant::now();
+            let mut position = 0;
+            while !should_stop.load(Ordering::Relaxed) {
+                if !is_playing.load(Ordering::Relaxed) {
+                    std::thread::sleep(period);
+                    deadline = Instant::now();
+                    continue;
+                }
+                if position == frames.len() {
+                    let _ = sender.send(AudioInputEvent::EndOfInput);
+                    break;
+                }
+
+                match pacing {
+                    NullPacing::RealTime => {
+                        let end = (position + Self::FRAMES_PER_CALLBACK).min(frames.len());
+                        push_frames(frames[position..end].iter().copied(), &queue, &sender);
+                        position = end;
+
+                        deadline += period;
+                        let now = Instant::now();
+                        if deadline > now {
+                            std::thread::sleep(deadline - now);
+                        } else {
+                            deadline = now;
+                        }
+                    }
+                    NullPacing::AsFastAsPossible => {
+                        // Like a device that never drops anything: push only
+                        // as much as the consumer has made room for.
+                        let room = queue.capacity() - queue.len();
+                        let end =
+                            (position + room.min(Self::FRAMES_PER_CALLBACK)).min(frames.len());
+                        if end == position {
+                            std::thread::sleep(Duration::from_micros(200));
+                            continue;
+                        }
+                        push_frames(frames[position..end].iter().copied(), &queue, &sender);
+                        position = end;
+                    }
+                }
+            }
+        }));
+    }
+
+    fn stop_producer(&mut self) {
+        self.should_stop.store(true, Ordering::Relaxed);
+        if let Some(handler) = self.handler.take() {
+            let _ = handler.join();
+        }
+    }
+}
+impl AudioInput for FileAudioInput {
+    fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    fn queue(&self) -> &AudioQueue {
+        &self.queue
+    }
+
+    fn play(&self) {
+        self.is_playing.store(true, Ordering::Relaxed);
+    }
+
+    fn pause(&self) {
+        self.is_playing.store(false, Ordering::Relaxed);
+    }
+}
+impl Drop for FileAudioInput {
+    fn drop(&mut self) {
+        self.stop_producer();
+    }
+}
+
+/// Pushes `frames` onto `queue`, then tells the consumer how many made it and
+/// how many didn't.
+fn push_frames(
+    frames: impl Iterator<Item = StereoSample>,
+    queue: &AudioQueue,
+    sender: &Sender<AudioInputEvent>,
+) {
+    let mut captured = 0;
+    let mut dropped = 0;
+    for frame in frames {
+        if queue.push(frame).is_ok() {
+            captured += 1;
+        } else {
+            dropped += 1;
+        }
+    }
+    let now = Instant::now();
+    if captured > 0 {
+        let _ = sender.send(AudioInputEvent::Captured(now, captured));
+    }
+    if dropped > 0 {
+        let _ = sender.send(AudioInputEvent::Overrun(now, dropped));
+    }
+}
+
+/// Delivers frames from an [AudioInput]'s queue at some other sample rate,
+/// so that a consumer running at that rate can mix them in frame for frame.
+#[derive(Debug)]
+pub struct InputFeed {
+    queue: AudioQueue,
+    resampler: Resampler,
+}
+impl InputFeed {
+    /// Converts the frames in `queue`, which arrive at `from_rate`, to
+    /// `to_rate`.
+    pub fn new(queue: AudioQueue, from_rate: usize, to_rate: usize) -> Self {
+        Self {
+            queue,
+            resampler: Resampler::new(from_rate, to_rate),
+        }
+    }
+
+    pub fn queue(&self) -> &AudioQueue {
+        &self.queue
+    }
+
+    pub fn to_rate(&self) -> usize {
+        self.resampler.to_rate()
+    }
+
+    /// Delivers frames at `to_rate` from now on. Anything already converted
+    /// is thrown away.
+    pub fn set_to_rate(&mut self, to_rate: usize) {
+        if to_rate != self.resampler.to_rate() {
+            self.resampler = Resampler::new(self.resampler.from_rate(), to_rate);
+        }
+    }
+
+    /// Fills `samples` with the next captured frames. If the input hasn't
+    /// kept up, the frames it's missing are silent. Returns how many input
+    /// frames were missing.
+    pub fn pull(&mut self, samples: &mut [StereoSample]) -> usize {
+        let mut missing = 0;
+        for _ in 0..self.resampler.input_frames_needed(samples.len()) {
+            let frame = self.queue.pop().unwrap_or_else(|| {
+                missing += 1;
+                StereoSample::SILENCE
+            });
+            self.resampler.push(&[frame]);
+        }
+        self.resampler.pull(samples);
+        missing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam_channel::unbounded;
+
+    fn ramp(len: usize) -> Vec<StereoSample> {
+        (0..len)
+            .map(|i| {
+                let value = i as SampleType / len as SampleType;
+                StereoSample(GrooveSample(value), GrooveSample(-value))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn on_capture_converts_and_duplicates_mono() {
+        let queue: AudioQueue = Arc::new(ArrayQueue::new(4));
+        let (sender, receiver) = unbounded();
+        AudioInputStream::on_capture(&[i16::MIN, 0], 1, &queue, &sender);
+        assert_eq!(queue.pop().map(|s| (s.0 .0, s.1 .0)), Some((-1.0, -1.0)));
+        assert_eq!(queue.pop().map(|s| (s.0 .0, s.1 .0)), Some((0.0, 0.0)));
+        assert!(matches!(
+            receiver.try_recv(),
+            Ok(AudioInputEvent::Captured(_, 2))
+        ));
+    }
+
+    #[test]
+    fn on_capture_keeps_first_two_channels_and_reports_overruns() {
+        let queue: AudioQueue = Arc::new(ArrayQueue::new(1));
+        let (sender, receiver) = unbounded();
+        AudioInputStream::on_capture(&[0.5f32, -0.5, 0.25, 0.1, 0.2, 0.3], 3, &queue, &sender);
+        assert_eq!(queue.pop().map(|s| (s.0 .0, s.1 .0)), Some((0.5, -0.5)));
+        assert!(matches!(
+            receiver.try_recv(),
+            Ok(AudioInputEvent::Captured(_, 1))
+        ));
+        assert!(matches!(
+            receiver.try_recv(),
+            Ok(AudioInputEvent::Overrun(_, 1))
+        ));
+    }
+
+    #[test]
+    fn file_input_delivers_every_frame_in_order() {
+        let (sender, receiver) = unbounded();
+        let frames = ramp(5000);
+        let input = FileAudioInput::new_from_samples(
+            frames.clone(),
+            44100,
+            256,
+            NullPacing::AsFastAsPossible,
+            sender,
+        );
+
+        let mut captured = Vec::default();
+        let start = Instant::now();
+        while captured.len() < frames.len() && start.elapsed() < Duration::from_secs(5) {
+            match input.queue().pop() {
+                Some(frame) => captured.push(frame),
+                None => std::thread::sleep(Duration::from_micros(100)),
+            }
+        }
+        let as_tuples =
+            |frames: &[StereoSample]| frames.iter().map(|s| (s.0 .0, s.1 .0)).collect::<Vec<_>>();
+        assert_eq!(as_tuples(&captured), as_tuples(&frames));
+
+        // The producer thread drops its sender when it finishes.
+        let events: Vec<_> = receiver.iter().collect();
+        assert!(events
+            .iter()
+            .any(|e| matches!(e, AudioInputEvent::EndOfInput)));
+        assert!(!events
+            .iter()
+            .any(|e| matches!(e, AudioInputEvent::Overrun(..))));
+    }
+
+    #[test]
+    fn file_input_reads_wav() {
+        let path =
+            std::env::temp_dir().join(format!("egui-prototype-input-{}.wav", std::process::id()));
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 22050,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        writer.write_sample(16384i16).unwrap();
+        writer.write_sample(-16384i16).unwrap();
+        writer.finalize().unwrap();
+
+        let (sender, _receiver) = unbounded();
+        let input =
+            FileAudioInput::new_with(&path, 16, NullPacing::AsFastAsPossible, sender).unwrap();
+        assert_eq!(input.sample_rate(), 22050);
+        let start = Instant::now();
+        while input.queue().len() < 2 && start.elapsed() < Duration::from_secs(1) {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(
+            input.queue().pop().map(|s| (s.0 .0, s.1 .0)),
+            Some((0.5, 0.5))
+        );
+        assert_eq!(
+            input.queue().pop().map(|s| (s.0 .0, s.1 .0)),
+            Some((-0.5, -0.5))
+        );
+    }
+
+    #[test]
+    fn input_feed_converts_rates_and_pads_with_silence() {
+        let queue: AudioQueue = Arc::new(ArrayQueue::new(1024));
+        for _ in 0..1024 {
+            let _ = queue.push(StereoSample(GrooveSample(0.5), GrooveSample(0.5)));
+        }
+
+        // Half the rate needs roughly twice the input.
+        let mut feed = InputFeed::new(Arc::clone(&queue), 48000, 24000);
+        let mut samples = [StereoSample::SILENCE; 256];
+        assert_eq!(feed.pull(&mut samples), 0);
+        assert!(queue.len() < 1024 - 2 * 256 + 64);
+        assert!((samples[200].0 .0 - 0.5).abs() < 0.01);
+
+        // Once the queue runs dry, the rest is silence.
+        let mut samples = [StereoSample::SILENCE; 1024];
+        assert!(feed.pull(&mut samples) > 0);
+        assert!(samples[1000].0 .0.abs() < 1e-6);
+
+        feed.set_to_rate(48000);
+        assert_eq!(feed.to_rate(), 48000);
+    }
+}
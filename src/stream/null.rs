@@ -0,0 +1,311 @@
+use super::{AudioBackend, AudioInterfaceEvent, AudioQueue, AudioStreamError};
+use crossbeam::queue::ArrayQueue;
+use crossbeam_channel::Sender;
+use std::{
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// How a [NullAudioStream] decides when to consume the next chunk of samples.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NullPacing {
+    /// Consume samples at the same rate that a real audio interface would.
+    #[default]
+    RealTime,
+
+    /// Consume samples as soon as the producer supplies them.
+    AsFastAsPossible,
+}
+
+/// An [AudioBackend] that isn't connected to any audio hardware. It runs its
+/// own thread that pulls samples from the [AudioQueue] and throws them away,
+/// sending [AudioInterfaceEvent::NeedsAudio] just as a cpal callback would.
+/// This lets the rest of the pipeline run on machines without a sound card.
+pub struct NullAudioStream {
+    sample_rate: usize,
+    pacing: NullPacing,
+
+    // The queue of samples that the stream consumes.
+    queue: AudioQueue,
+
+    // The cue bus, consumed in step with `queue`.
+    cue: AudioQueue,
+
+    // The sending half of the channel that the stream uses to send updates to
+    // the subscription.
+    sender: Sender<AudioInterfaceEvent>,
+
+    // Whether the consumer thread should be consuming samples.
+    is_playing: Arc<AtomicBool>,
+
+    // How many samples the consumer thread has taken from the queue, across
+    // all the queues it has ever consumed.
+    samples_consumed: Arc<AtomicUsize>,
+
+    // Tells the current consumer thread to exit.
+    should_stop: Arc<AtomicBool>,
+
+    // The current consumer thread.
+    handler: Option<JoinHandle<()>>,
+}
+impl Debug for NullAudioStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NullAudioStream")
+            .field("sample_rate", &self.sample_rate)
+            .field("pacing", &self.pacing)
+            .field("queue", &self.queue)
+            .field("samples_consumed", &self.samples_consumed)
+            .finish()
+    }
+}
+impl NullAudioStream {
+    /// The sample rate a [NullAudioStream] uses when the caller has no
+    /// particular reason to pick a different one.
+    pub const DEFAULT_SAMPLE_RATE: usize = 44100;
+
+    /// How many samples the consumer thread takes on each pass. This is in the
+    /// same ballpark as what a typical audio interface asks for per callback.
+    const FRAMES_PER_CALLBACK: usize = 512;
+
+    /// How long the consumer thread waits for the producer when pacing is
+    /// [NullPacing::AsFastAsPossible] and the queue is empty.
+    const IDLE_WAIT: Duration = Duration::from_micros(200);
+
+    pub fn new_with(
+        sample_rate: usize,
+        buffer_size: usize,
+        pacing: NullPacing,
+        sender: Sender<AudioInterfaceEvent>,
+    ) -> Self {
+        let mut r = Self {
+            sample_rate,
+            pacing,
+            queue: Arc::new(ArrayQueue::new(buffer_size)),
+            cue: Arc::new(ArrayQueue::new(buffer_size)),
+            sender,
+            is_playing: Arc::new(AtomicBool::new(true)),
+            samples_consumed: Default::default(),
+            should_stop: Default::default(),
+            handler: None,
+        };
+        r.send_reset();
+        r.start_consumer();
+        r
+    }
+
+    /// Returns the total number of samples that have been taken from the
+    /// queue since the stream was created.
+    pub fn samples_consumed(&self) -> usize {
+        self.samples_consumed.load(Ordering::Relaxed)
+    }
+
+    fn start_consumer(&mut self) {
+        let should_stop = Arc::new(AtomicBool::new(false));
+        self.should_stop = Arc::clone(&should_stop);
+        let queue = Arc::clone(&self.queue);
+        let cue = Arc::clone(&self.cue);
+        let sender = self.sender.clone();
+        let is_playing = Arc::clone(&self.is_playing);
+        let samples_consumed = Arc::clone(&self.samples_consumed);
+        let pacing = self.pacing;
+        let period =
+            Duration::from_secs_f64(Self::FRAMES_PER_CALLBACK as f64 / self.sample_rate as f64);
+
+        self.handler = Some(std::thread::spawn(move || {
+            let mut deadline = Instant::now();
+            let mut awaiting_audio = false;
+            while !should_stop.load(Ordering::Relaxed) {
+                if !is_playing.load(Ordering::Relaxed) {
+                    std::thread::sleep(period);
+                    deadline = Instant::now();
+                    continue;
+                }
+
+                let mut consumed = 0;
+                while consumed < Self::FRAMES_PER_CALLBACK && queue.pop().is_some() {
+                    let _ = cue.pop();
+                    consumed += 1;
+                }
+                samples_consumed.fetch_add(consumed, Ordering::Relaxed);
+
+                // Only real-time pacing can underrun; otherwise we just wait
+                // for the producer.
+                if pacing == NullPacing::RealTime && consumed < Self::FRAMES_PER_CALLBACK {
+                    let _ = sender.send(AudioInterfaceEvent::Underrun(
+                        Instant::now(),
+                        Self::FRAMES_PER_CALLBACK - consumed,
+                    ));
+                }
+
+                // Ask only once per batch of consumed samples. Otherwise, in
+                // AsFastAsPossible mode we'd flood the producer with requests
+                // while it's still working on the last one.
+                if consumed > 0 {
+                    awaiting_audio = false;
+                }
+                let capacity = queue.capacity();
+                let len = queue.len();
+                if len < capacity && !awaiting_audio {
+                    awaiting_audio = true;
+                    let _ = sender.send(AudioInterfaceEvent::NeedsAudio(
+                        Instant::now(),
+                        capacity - len,
+                    ));
+                }
+
+                match pacing {
+                    NullPacing::RealTime => {
+                        deadline += period;
+                        let now = Instant::now();
+                        if deadline > now {
+                            std::thread::sleep(deadline - now);
+                        } else {
+                            // We fell behind. Don't try to catch up, just as
+                            // a real interface wouldn't.
+                            deadline = now;
+                        }
+                    }
+                    NullPacing::AsFastAsPossible => {
+                        if consumed == 0 {
+                            std::thread::sleep(Self::IDLE_WAIT);
+                        }
+                    }
+                }
+            }
+        }));
+    }
+
+    fn stop_consumer(&mut self) {
+        self.should_stop.store(true, Ordering::Relaxed);
+        if let Some(handler) = self.handler.take() {
+            let _ = handler.join();
+        }
+    }
+
+    fn send_reset(&self) {
+        let _ = self.sender.send(AudioInterfaceEvent::Reset(
+            self.sample_rate,
+            Arc::clone(&self.queue),
+            Arc::clone(&self.cue),
+        ));
+    }
+}
+impl AudioBackend for NullAudioStream {
+    fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    fn buffer_size(&self) -> usize {
+        self.queue.capacity()
+    }
+
+    /// Restarts the consumer thread on the new queue.
+    fn set_buffer_size(&mut self, buffer_size: usize) -> Result<(), AudioStreamError> {
+        if buffer_size == 0 {
+            return Err(AudioStreamError::InvalidBufferSize(buffer_size));
+        }
+        self.stop_consumer();
+        self.queue = Arc::new(ArrayQueue::new(buffer_size));
+        self.cue = Arc::new(ArrayQueue::new(buffer_size));
+        self.send_reset();
+        self.start_consumer();
+        Ok(())
+    }
+
+    fn play(&self) -> Result<(), AudioStreamError> {
+        self.is_playing.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn pause(&self) -> Result<(), AudioStreamError> {
+        self.is_playing.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn quit(&mut self) {
+        self.stop_consumer();
+        let _ = self.sender.send(AudioInterfaceEvent::Quit);
+    }
+}
+impl Drop for NullAudioStream {
+    fn drop(&mut self) {
+        self.stop_consumer();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam_channel::unbounded;
+    use groove_core::StereoSample;
+
+    #[test]
+    fn as_fast_as_possible_consumes_everything_produced() {
+        let (sender, receiver) = unbounded();
+        let mut stream =
+            NullAudioStream::new_with(44100, 256, NullPacing::AsFastAsPossible, sender);
+        let queue = match receiver.recv() {
+            Ok(AudioInterfaceEvent::Reset(sample_rate, queue, _)) => {
+                assert_eq!(sample_rate, 44100);
+                queue
+            }
+            other => panic!("expected Reset, got {other:?}"),
+        };
+
+        let mut produced = 0;
+        while produced < 10_000 {
+            match receiver.recv_timeout(Duration::from_secs(1)) {
+                Ok(AudioInterfaceEvent::NeedsAudio(_, count)) => {
+                    for _ in 0..count {
+                        if queue.push(StereoSample::SILENCE).is_ok() {
+                            produced += 1;
+                        }
+                    }
+                }
+                other => panic!("expected NeedsAudio, got {other:?}"),
+            }
+        }
+
+        let start = Instant::now();
+        while stream.samples_consumed() < produced && start.elapsed() < Duration::from_secs(1) {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(stream.samples_consumed(), produced);
+
+        stream.quit();
+        assert!(receiver
+            .iter()
+            .any(|event| matches!(event, AudioInterfaceEvent::Quit)));
+    }
+
+    #[test]
+    fn paused_stream_consumes_nothing() {
+        let (sender, receiver) = unbounded();
+        let stream = NullAudioStream::new_with(44100, 256, NullPacing::AsFastAsPossible, sender);
+        stream.pause().unwrap();
+        let queue = match receiver.recv() {
+            Ok(AudioInterfaceEvent::Reset(_, queue, _)) => queue,
+            other => panic!("expected Reset, got {other:?}"),
+        };
+
+        // Give the consumer thread a chance to notice that it's paused.
+        std::thread::sleep(Duration::from_millis(20));
+        let before = stream.samples_consumed();
+        while queue.push(StereoSample::SILENCE).is_ok() {}
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(stream.samples_consumed(), before);
+        assert!(queue.is_full());
+
+        stream.play().unwrap();
+        let start = Instant::now();
+        while !queue.is_empty() && start.elapsed() < Duration::from_secs(1) {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,296 @@
+use super::{AudioDeviceState, AudioInterfaceEvent};
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// Keeps track of how well the producer is keeping up with the audio stream,
+/// and what state the device is in, based on the events that the stream
+/// sends. Feed it every
+/// [AudioInterfaceEvent] with [AudioStats::handle_event()]. Underruns are
+/// the exception: whoever receives them from the stream should record them
+/// in [AudioStats::underrun_counter()] instead, so that none go missing on
+/// the way to us.
+#[derive(Debug, Default)]
+pub struct AudioStats {
+    // The capacity of the queue named in the most recent Reset.
+    capacity: usize,
+
+    // When the previous NeedsAudio was sent, so we can measure the interval
+    // between callbacks.
+    last_needs_audio: Option<Instant>,
+
+    callback_intervals: VecDeque<Duration>,
+    max_callback_interval: Duration,
+
+    // How many samples were left in the queue at the end of each callback.
+    fill_levels: VecDeque<usize>,
+
+    underruns: Arc<UnderrunCounter>,
+
+    device_state: AudioDeviceState,
+
+    // How many output channels the most recently connected device has.
+    output_channels: Option<usize>,
+}
+impl AudioStats {
+    /// How many of the most recent intervals and fill levels we keep around.
+    pub const HISTORY_LEN: usize = 256;
+
+    /// Updates the statistics with an event from the audio stream. Events that
+    /// don't say anything about performance are ignored, and so are
+    /// underruns, which arrive through [AudioStats::underrun_counter()].
+    pub fn handle_event(&mut self, event: &AudioInterfaceEvent) {
+        match event {
+            AudioInterfaceEvent::Reset(_, queue, _) => {
+                // The new stream's timing has nothing to do with the old one's.
+                self.capacity = queue.capacity();
+                self.last_needs_audio = None;
+            }
+            AudioInterfaceEvent::NeedsAudio(when, count) => {
+                if let Some(last) = self.last_needs_audio {
+                    let interval = when.saturating_duration_since(last);
+                    self.max_callback_interval = self.max_callback_interval.max(interval);
+                    Self::push_bounded(&mut self.callback_intervals, interval);
+                }
+                self.last_needs_audio = Some(*when);
+                Self::push_bounded(&mut self.fill_levels, self.capacity.saturating_sub(*count));
+            }
+            AudioInterfaceEvent::DeviceState(state) => self.device_state = *state,
+            AudioInterfaceEvent::OutputChannels(count) => self.output_channels = Some(*count),
+            _ => {}
+        }
+    }
+
+    /// Forgets everything except what's true of the current queue and device.
+    pub fn clear(&mut self) {
+        self.underruns.clear();
+        *self = Self {
+            capacity: self.capacity,
+            device_state: self.device_state,
+            output_channels: self.output_channels,
+            underruns: Arc::clone(&self.underruns),
+            ..Default::default()
+        };
+    }
+
+    /// Returns the counter that underruns should be recorded in. It's shared,
+    /// so the thread that hears about them can record them directly.
+    pub fn underrun_counter(&self) -> Arc<UnderrunCounter> {
+        Arc::clone(&self.underruns)
+    }
+
+    /// Returns the capacity of the current queue.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns what the service most recently said about the device.
+    pub fn device_state(&self) -> AudioDeviceState {
+        self.device_state
+    }
+
+    /// Returns how many output channels the device has, if the service has
+    /// connected to one.
+    pub fn output_channels(&self) -> Option<usize> {
+        self.output_channels
+    }
+
+    /// Returns the number of callbacks that ran out of samples.
+    pub fn underrun_count(&self) -> usize {
+        self.underruns.count()
+    }
+
+    /// Returns the total number of frames that were filled with silence
+    /// because the queue was empty.
+    pub fn underrun_frames(&self) -> usize {
+        self.underruns.frames()
+    }
+
+    /// Returns when the most recent underrun happened, if there's been one.
+    pub fn last_underrun(&self) -> Option<Instant> {
+        self.underruns.last()
+    }
+
+    /// Returns how full the queue was after each recent callback, oldest
+    /// first.
+    pub fn fill_levels(&self) -> impl Iterator<Item = &usize> {
+        self.fill_levels.iter()
+    }
+
+    /// Returns the recent intervals between callbacks, oldest first.
+    pub fn callback_intervals(&self) -> impl Iterator<Item = &Duration> {
+        self.callback_intervals.iter()
+    }
+
+    /// Returns the longest interval between callbacks since the stats were
+    /// last cleared.
+    pub fn max_callback_interval(&self) -> Duration {
+        self.max_callback_interval
+    }
+
+    /// Returns the mean of the recent intervals between callbacks.
+    pub fn mean_callback_interval(&self) -> Option<Duration> {
+        if self.callback_intervals.is_empty() {
+            None
+        } else {
+            Some(
+                self.callback_intervals.iter().sum::<Duration>()
+                    / self.callback_intervals.len() as u32,
+            )
+        }
+    }
+
+    fn push_bounded<T>(history: &mut VecDeque<T>, value: T) {
+        if history.len() == Self::HISTORY_LEN {
+            history.pop_front();
+        }
+        history.push_back(value);
+    }
+}
+
+/// Counts underruns as they're reported. The thread that receives them from
+/// the stream records, and the UI reads, so unlike a queue of events this
+/// can't fall behind and drop any.
+#[derive(Debug)]
+pub struct UnderrunCounter {
+    count: AtomicUsize,
+    frames: AtomicUsize,
+
+    // When the most recent underrun happened, as nanoseconds after `epoch`,
+    // plus one so that zero can mean there hasn't been one.
+    epoch: Instant,
+    last: AtomicU64,
+}
+impl Default for UnderrunCounter {
+    fn default() -> Self {
+        Self {
+            count: Default::default(),
+            frames: Default::default(),
+            epoch: Instant::now(),
+            last: Default::default(),
+        }
+    }
+}
+impl UnderrunCounter {
+    /// Records that the stream played `frames` frames of silence at `when`.
+    pub fn record(&self, when: Instant, frames: usize) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.frames.fetch_add(frames, Ordering::Relaxed);
+        let nanos = when.saturating_duration_since(self.epoch).as_nanos() as u64;
+        self.last.store(nanos + 1, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames.load(Ordering::Relaxed)
+    }
+
+    pub fn last(&self) -> Option<Instant> {
+        match self.last.load(Ordering::Relaxed) {
+            0 => None,
+            nanos => Some(self.epoch + Duration::from_nanos(nanos - 1)),
+        }
+    }
+
+    pub fn clear(&self) {
+        self.count.store(0, Ordering::Relaxed);
+        self.frames.store(0, Ordering::Relaxed);
+        self.last.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam::queue::ArrayQueue;
+    use std::sync::Arc;
+
+    #[test]
+    fn tracks_intervals_fill_levels_and_underruns() {
+        let mut stats = AudioStats::default();
+        stats.handle_event(&AudioInterfaceEvent::Reset(
+            44100,
+            Arc::new(ArrayQueue::new(100)),
+            Arc::new(ArrayQueue::new(100)),
+        ));
+        assert_eq!(stats.capacity(), 100);
+
+        let start = Instant::now();
+        for (ms, count) in [(0, 10), (10, 20), (30, 100)] {
+            stats.handle_event(&AudioInterfaceEvent::NeedsAudio(
+                start + Duration::from_millis(ms),
+                count,
+            ));
+        }
+        stats.underrun_counter().record(start, 64);
+        stats.handle_event(&AudioInterfaceEvent::DeviceState(AudioDeviceState::Paused));
+        stats.handle_event(&AudioInterfaceEvent::OutputChannels(6));
+
+        assert_eq!(
+            stats.callback_intervals().copied().collect::<Vec<_>>(),
+            vec![Duration::from_millis(10), Duration::from_millis(20)]
+        );
+        assert_eq!(stats.max_callback_interval(), Duration::from_millis(20));
+        assert_eq!(
+            stats.mean_callback_interval(),
+            Some(Duration::from_millis(15))
+        );
+        assert_eq!(
+            stats.fill_levels().copied().collect::<Vec<_>>(),
+            vec![90, 80, 0]
+        );
+        assert_eq!(stats.underrun_count(), 1);
+        assert_eq!(stats.underrun_frames(), 64);
+        assert_eq!(stats.last_underrun(), Some(start));
+
+        stats.clear();
+        assert_eq!(stats.capacity(), 100);
+        assert_eq!(stats.device_state(), AudioDeviceState::Paused);
+        assert_eq!(stats.output_channels(), Some(6));
+        assert_eq!(stats.underrun_count(), 0);
+        assert!(stats.last_underrun().is_none());
+        assert!(stats.mean_callback_interval().is_none());
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let mut stats = AudioStats::default();
+        let start = Instant::now();
+        for i in 0..AudioStats::HISTORY_LEN * 2 {
+            stats.handle_event(&AudioInterfaceEvent::NeedsAudio(
+                start + Duration::from_millis(i as u64),
+                0,
+            ));
+        }
+        assert_eq!(stats.fill_levels().count(), AudioStats::HISTORY_LEN);
+        assert_eq!(stats.callback_intervals().count(), AudioStats::HISTORY_LEN);
+    }
+
+    #[test]
+    fn underruns_are_counted_from_another_thread() {
+        let stats = AudioStats::default();
+        let counter = stats.underrun_counter();
+        let start = Instant::now();
+        std::thread::spawn(move || {
+            for i in 0..1000 {
+                counter.record(start + Duration::from_millis(i), 2);
+            }
+        })
+        .join()
+        .unwrap();
+        assert_eq!(stats.underrun_count(), 1000);
+        assert_eq!(stats.underrun_frames(), 2000);
+        assert_eq!(
+            stats.last_underrun(),
+            Some(start + Duration::from_millis(999))
+        );
+    }
+}